@@ -0,0 +1,207 @@
+use std::str::FromStr;
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use semver::{BuildMetadata, Prerelease, Version};
+use serde::Serialize;
+
+#[derive(Clone, ValueEnum, Default, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Component {
+    Major,
+    Minor,
+    #[default]
+    Patch,
+    Prerelease,
+    #[value(alias = "finalize")]
+    Release,
+    Auto,
+}
+
+/// Increments the given component of the version according to the SemVer spec.
+///
+/// Bumping a component resets all lower components to zero, so bumping 1.8.5
+/// by a minor version yields 1.9.0. Release bumps drop both prerelease and
+/// build metadata, while prerelease bumps only drop the build metadata.
+///
+/// If a prerelease label is given, release bumps start a new prerelease line
+/// with that label, such that bumping 1.4.2 by a minor version with the label
+/// 'rc' yields 1.5.0-rc.1. Prerelease bumps switch to the label if it differs
+/// from the current one, such that 1.5.0-alpha.3 becomes 1.5.0-beta.1.
+///
+/// Releasing a prerelease drops the prerelease, such that 2.0.0-rc.4 becomes
+/// 2.0.0, and fails if the version is not a prerelease.
+pub fn increment(
+    version: &Version,
+    component: &Component,
+    label: Option<&str>,
+) -> Result<Version, anyhow::Error> {
+    let mut new_version = version.clone();
+    new_version.build = BuildMetadata::EMPTY;
+
+    match component {
+        Component::Major => {
+            new_version.major += 1;
+            new_version.minor = 0;
+            new_version.patch = 0;
+            new_version.pre = Prerelease::EMPTY;
+        }
+        Component::Minor => {
+            new_version.minor += 1;
+            new_version.patch = 0;
+            new_version.pre = Prerelease::EMPTY;
+        }
+        Component::Patch => {
+            new_version.patch += 1;
+            new_version.pre = Prerelease::EMPTY;
+        }
+        Component::Prerelease => {
+            if version.pre.is_empty() {
+                bail!(
+                    "{version} is not a prerelease, specify major, minor or patch \
+                    to start a new prerelease line"
+                );
+            }
+
+            let prerelease = &new_version.pre;
+
+            let (head, prerelease_version) =
+                prerelease.rsplit_once('.').unwrap_or(("", prerelease));
+
+            if let Some(label) = label.filter(|label| *label != head) {
+                new_version.pre = first_prerelease(label)?;
+
+                if new_version <= *version {
+                    bail!(
+                        "switching to the prerelease label '{label}' would yield \
+                        {new_version}, which precedes {version}"
+                    );
+                }
+
+                return Ok(new_version);
+            }
+
+            let bumped_pre = prerelease_version
+                .parse::<u64>()
+                .with_context(|| "numeric part of prerelease is not a valid non-zero integer")?
+                + 1;
+
+            new_version.pre = if head.is_empty() {
+                Prerelease::from_str(&format!("{bumped_pre}"))
+            } else {
+                Prerelease::from_str(&format!("{head}.{bumped_pre}"))
+            }
+            .with_context(|| "failed to rebuild prerelease tag after increment")?;
+
+            return Ok(new_version);
+        }
+        Component::Release => {
+            if version.pre.is_empty() {
+                bail!("{version} is already a release, there is no prerelease to finalize");
+            }
+
+            if label.is_some() {
+                bail!("a prerelease label cannot be used when finalizing a release");
+            }
+
+            new_version.pre = Prerelease::EMPTY;
+        }
+        Component::Auto => {
+            bail!("the component to bump must be inferred from history before incrementing");
+        }
+    }
+
+    if let Some(label) = label {
+        new_version.pre = first_prerelease(label)?;
+    }
+
+    Ok(new_version)
+}
+
+/// Builds the first prerelease of a prerelease line, such as 'rc.1'.
+fn first_prerelease(label: &str) -> Result<Prerelease, anyhow::Error> {
+    Prerelease::from_str(&format!("{label}.1"))
+        .with_context(|| format!("'{label}' is not a valid prerelease label"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bump(version: &str, component: Component, label: Option<&str>) -> String {
+        increment(&Version::parse(version).unwrap(), &component, label)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn resets_lower_components() {
+        assert_eq!(bump("1.8.5", Component::Major, None), "2.0.0");
+        assert_eq!(bump("1.8.5", Component::Minor, None), "1.9.0");
+        assert_eq!(bump("1.8.5", Component::Patch, None), "1.8.6");
+    }
+
+    #[test]
+    fn drops_prerelease_and_build_metadata() {
+        assert_eq!(bump("1.8.5-beta.2+abc", Component::Minor, None), "1.9.0");
+        assert_eq!(bump("1.8.5-beta.2+abc", Component::Patch, None), "1.8.6");
+    }
+
+    #[test]
+    fn increments_numeric_prerelease() {
+        assert_eq!(
+            bump("0.0.1-beta.1", Component::Prerelease, None),
+            "0.0.1-beta.2"
+        );
+        assert_eq!(
+            bump("0.0.1-alpha.3", Component::Prerelease, None),
+            "0.0.1-alpha.4"
+        );
+        assert_eq!(
+            bump("0.0.1-test.b.2", Component::Prerelease, None),
+            "0.0.1-test.b.3"
+        );
+        assert_eq!(bump("0.0.1-7", Component::Prerelease, None), "0.0.1-8");
+    }
+
+    #[test]
+    fn rejects_non_numeric_prerelease() {
+        for version in ["0.0.1", "0.0.1-beta", "0.0.1-alpha1", "0.0.1-beta.1.a"] {
+            let version = Version::parse(version).unwrap();
+            assert!(increment(&version, &Component::Prerelease, None).is_err());
+        }
+    }
+
+    #[test]
+    fn starts_prerelease_line() {
+        assert_eq!(bump("1.4.2", Component::Minor, Some("rc")), "1.5.0-rc.1");
+        assert_eq!(
+            bump("1.4.2", Component::Major, Some("alpha")),
+            "2.0.0-alpha.1"
+        );
+    }
+
+    #[test]
+    fn switches_prerelease_label() {
+        assert_eq!(
+            bump("1.5.0-alpha.3", Component::Prerelease, Some("beta")),
+            "1.5.0-beta.1"
+        );
+        assert_eq!(
+            bump("1.5.0-beta.1", Component::Prerelease, Some("beta")),
+            "1.5.0-beta.2"
+        );
+
+        // Switching to a label which sorts before the current one would go backwards.
+        let version = Version::parse("1.5.0-beta.2").unwrap();
+        assert!(increment(&version, &Component::Prerelease, Some("alpha")).is_err());
+    }
+
+    #[test]
+    fn finalizes_prerelease() {
+        assert_eq!(bump("2.0.0-rc.4", Component::Release, None), "2.0.0");
+
+        let version = Version::parse("2.0.0").unwrap();
+        assert!(increment(&version, &Component::Release, None).is_err());
+    }
+}
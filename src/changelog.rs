@@ -0,0 +1,212 @@
+use git2::{Commit, Repository, Time};
+use semver::Version;
+
+use crate::{conventional::ConventionalCommit, tags::VersionTag};
+
+/// Headings of the changelog sections, along with the commit types listed
+/// under each of them. Breaking changes are listed separately, before these.
+const SECTIONS: &[(&str, &[&str])] = &[
+    ("Added", &["feat"]),
+    ("Fixed", &["fix"]),
+    ("Changed", &["refactor", "perf", "revert"]),
+    ("Documentation", &["docs"]),
+];
+
+/// Renders a Keep a Changelog style section for the version, listing the
+/// given commits grouped by their Conventional Commit type.
+pub fn section(
+    repository: &Repository,
+    version: &Version,
+    commits: &[Commit],
+) -> Result<String, anyhow::Error> {
+    let mut entries = Vec::new();
+    for commit in commits {
+        let Some(parsed) = commit.message().and_then(ConventionalCommit::parse) else {
+            continue;
+        };
+
+        let short_id = commit.as_object().short_id()?;
+        let short_id = short_id.as_str().unwrap_or_default();
+
+        let entry = match &parsed.scope {
+            Some(scope) => format!("- **{scope}:** {} ({short_id})\n", parsed.description),
+            None => format!("- {} ({short_id})\n", parsed.description),
+        };
+
+        entries.push((parsed, entry));
+    }
+
+    let mut groups = vec![(
+        "Breaking Changes",
+        entries
+            .iter()
+            .filter(|(parsed, _)| parsed.breaking)
+            .collect::<Vec<_>>(),
+    )];
+
+    groups.extend(SECTIONS.iter().map(|(heading, kinds)| {
+        (
+            *heading,
+            entries
+                .iter()
+                .filter(|(parsed, _)| !parsed.breaking && kinds.contains(&parsed.kind.as_str()))
+                .collect(),
+        )
+    }));
+
+    let date = format_date(repository.signature()?.when());
+    let mut section = format!("## [{version}] - {date}\n");
+
+    for (heading, group) in groups {
+        if group.is_empty() {
+            continue;
+        }
+
+        section.push_str(&format!("\n### {heading}\n"));
+        for (_, entry) in group {
+            section.push_str(entry);
+        }
+    }
+
+    Ok(section)
+}
+
+/// Lists the subject of each commit made since the latest version, if there
+/// is one, for use as release notes in the annotation of a tag.
+pub fn notes(latest: Option<&VersionTag>, commits: &[Commit]) -> String {
+    let since = latest
+        .map(|latest| format!(" since {}", latest.name))
+        .unwrap_or_default();
+
+    if commits.is_empty() {
+        return format!("No changes{since}.\n");
+    }
+
+    let mut notes = format!("Changes{since}:\n\n");
+    for commit in commits {
+        notes.push_str(&format!("- {}\n", commit.summary().unwrap_or_default()));
+    }
+
+    notes
+}
+
+/// Inserts the section into the contents of a changelog, above the most
+/// recently released version, starting a new changelog if it is empty. The
+/// title, introduction and any 'Unreleased' section are kept at the top.
+pub fn prepend(existing: &str, section: &str) -> String {
+    let existing = if existing.trim().is_empty() {
+        "# Changelog\n"
+    } else {
+        existing
+    };
+
+    let mut offset = 0;
+    let position = existing
+        .split_inclusive('\n')
+        .find_map(|line| {
+            let start = offset;
+            offset += line.len();
+
+            (line.starts_with("## ") && !line.to_lowercase().contains("unreleased"))
+                .then_some(start)
+        })
+        .unwrap_or(existing.len());
+
+    let (head, tail) = existing.split_at(position);
+    let mut content = format!("{}\n\n{section}", head.trim_end());
+    if !tail.is_empty() {
+        content.push('\n');
+        content.push_str(tail);
+    }
+
+    content
+}
+
+/// Formats the time as a calendar date (YYYY-MM-DD) in its own timezone.
+pub fn format_date(time: Time) -> String {
+    let days = (time.seconds() + i64::from(time.offset_minutes()) * 60).div_euclid(86400);
+
+    // Converts days since the unix epoch into a civil date, as described in
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+
+    const SECTION: &str = "## [1.1.0] - 2023-02-14\n\n### Added\n- support arrays (3f2a9c1)\n";
+
+    #[test]
+    fn starts_new_changelog() {
+        assert_eq!(prepend("", SECTION), format!("# Changelog\n\n{SECTION}"));
+        assert_eq!(
+            prepend("\n\n", SECTION),
+            format!("# Changelog\n\n{SECTION}")
+        );
+    }
+
+    #[test]
+    fn inserts_above_latest_version() {
+        let existing = indoc! {"
+            # Changelog
+
+            All notable changes are listed here.
+
+            ## [Unreleased]
+
+            ## [1.0.0] - 2023-01-09
+
+            ### Fixed
+            - handle empty input (9b1c0de)
+        "};
+
+        let expected = indoc! {"
+            # Changelog
+
+            All notable changes are listed here.
+
+            ## [Unreleased]
+
+            ## [1.1.0] - 2023-02-14
+
+            ### Added
+            - support arrays (3f2a9c1)
+
+            ## [1.0.0] - 2023-01-09
+
+            ### Fixed
+            - handle empty input (9b1c0de)
+        "};
+
+        assert_eq!(prepend(existing, SECTION), expected);
+    }
+
+    #[test]
+    fn appends_without_previous_versions() {
+        assert_eq!(
+            prepend("# Changelog\n\nIntroduction.\n", SECTION),
+            format!("# Changelog\n\nIntroduction.\n\n{SECTION}")
+        );
+    }
+
+    #[test]
+    fn formats_date_in_timezone() {
+        // 2023-02-14 23:30 UTC is already the next day at UTC+01:00.
+        assert_eq!(format_date(Time::new(1676417400, 0)), "2023-02-14");
+        assert_eq!(format_date(Time::new(1676417400, 60)), "2023-02-15");
+        assert_eq!(format_date(Time::new(0, 0)), "1970-01-01");
+    }
+}
@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use git2::{build::CheckoutBuilder, Index, Oid, Repository, ResetType, StatusOptions};
+
+/// Stages the given paths, which are relative to the root of the working
+/// directory unless absolute, and commits them on top of HEAD. Any other
+/// changes in the working directory or the index are left as they are, and
+/// are not part of the commit. Fails if any of the paths are ignored by git
+/// and not tracked already.
+pub fn commit_paths<P: AsRef<Path>>(
+    repository: &Repository,
+    paths: &[P],
+    message: &str,
+) -> Result<Oid, anyhow::Error> {
+    let head = repository.head()?.peel_to_commit()?;
+
+    // The tree of the commit is built from HEAD in memory, so only the given
+    // paths are taken from the index and previously staged changes to other
+    // files do not sneak into the commit.
+    let mut tree = Index::new()?;
+    tree.read_tree(&head.tree()?)?;
+
+    let workdir = repository
+        .workdir()
+        .with_context(|| "repository has no working directory")?;
+
+    let mut index = repository.index()?;
+    for path in paths {
+        let path = path.as_ref();
+        let path = path.strip_prefix(workdir).unwrap_or(path);
+
+        if index.get_path(path, 0).is_none() && repository.status_should_ignore(path)? {
+            bail!(
+                "{} is ignored by git, refusing to commit it",
+                path.display()
+            );
+        }
+
+        index.add_path(path)?;
+
+        let entry = index.get_path(path, 0).with_context(|| {
+            format!(
+                "{} was just staged, but is not in the index?",
+                path.display()
+            )
+        })?;
+        tree.add(&entry)?;
+    }
+    index.write()?;
+
+    let tree = repository.find_tree(tree.write_tree_to(repository)?)?;
+    let signature = repository.signature()?;
+
+    Ok(repository.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        message,
+        &tree,
+        &[&head],
+    )?)
+}
+
+/// Undoes the commits made on top of the given commit by moving HEAD back to
+/// it, and restores the given paths to their contents in that commit, in both
+/// the index and the working directory. Changes to other files are kept.
+pub fn undo_commit<P: AsRef<Path>>(
+    repository: &Repository,
+    original: Oid,
+    paths: &[P],
+) -> Result<(), anyhow::Error> {
+    let original = repository.find_object(original, None)?;
+    repository.reset(&original, ResetType::Soft, None)?;
+
+    let mut checkout = CheckoutBuilder::new();
+    checkout.force();
+    for path in paths {
+        checkout.path(path.as_ref());
+    }
+
+    repository.checkout_tree(&original, Some(&mut checkout))?;
+    Ok(())
+}
+
+/// Lists the tracked files which have uncommitted changes, either staged or
+/// in the working directory. Untracked and ignored files are not included.
+pub fn uncommitted_changes(repository: &Repository) -> Result<Vec<String>, anyhow::Error> {
+    if repository.is_bare() {
+        return Ok(Vec::new());
+    }
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+
+    Ok(repository
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::testing::TestRepository;
+
+    #[test]
+    fn undoes_release_commit() {
+        let test = TestRepository::new();
+        test.write("Cargo.toml", "version = \"1.0.0\"\n");
+        let original = test.commit("initial commit");
+
+        fs::write(test.path().join("Cargo.toml"), "version = \"1.0.1\"\n").unwrap();
+        fs::write(test.path().join("CHANGELOG.md"), "# Changelog\n").unwrap();
+        let paths = ["Cargo.toml", "CHANGELOG.md"];
+        let release = commit_paths(&test.repository, &paths, "chore(release): 1.0.1").unwrap();
+
+        let head = || test.repository.head().unwrap().target().unwrap();
+        assert_eq!(head(), release);
+
+        undo_commit(&test.repository, original, &paths).unwrap();
+
+        assert_eq!(head(), original);
+        assert_eq!(
+            fs::read_to_string(test.path().join("Cargo.toml")).unwrap(),
+            "version = \"1.0.0\"\n"
+        );
+        assert!(!test.path().join("CHANGELOG.md").exists());
+        assert!(uncommitted_changes(&test.repository).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_commit_ignored_files() {
+        let test = TestRepository::new();
+        test.write(".gitignore", "Cargo.lock\n");
+        test.commit("initial commit");
+
+        fs::write(test.path().join("Cargo.lock"), "").unwrap();
+        assert!(commit_paths(&test.repository, &["Cargo.lock"], "release").is_err());
+    }
+}
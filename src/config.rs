@@ -0,0 +1,453 @@
+use std::{collections::BTreeMap, ffi::OsString, fs, io::ErrorKind};
+
+use anyhow::{bail, Context};
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use git2::Repository;
+
+/// Name of the configuration file at the root of the repository.
+pub const CONFIG_FILE: &str = ".vergit.toml";
+
+/// Value of a setting, as written in either configuration source.
+enum Value {
+    Toml(toml::Value),
+    Git(Vec<String>),
+}
+
+/// Default options loaded from the repository's configuration, keyed by the
+/// subcommand they apply to, if any, and the long name of the option.
+///
+/// Options are read from the `.vergit.toml` file at the root of the repository:
+///
+/// ```toml
+/// prefix = "v"
+///
+/// [bump]
+/// push = true
+/// remote = "upstream"
+/// ```
+///
+/// If the file does not exist, they are read from the `[vergit]` section of
+/// the git config instead, where `[vergit "bump"]` applies only to bump.
+pub struct Config {
+    source: String,
+    settings: BTreeMap<(Option<String>, String), Value>,
+}
+
+impl Config {
+    pub fn load(repository: &Repository) -> Result<Self, anyhow::Error> {
+        if let Some(workdir) = repository.workdir() {
+            let path = workdir.join(CONFIG_FILE);
+            match fs::read_to_string(&path) {
+                Ok(content) => return Config::from_toml(&content),
+                Err(error) if error.kind() == ErrorKind::NotFound => {}
+                Err(error) => {
+                    return Err(error).with_context(|| format!("failed to read {}", path.display()))
+                }
+            }
+        }
+
+        Config::from_git(repository)
+    }
+
+    fn from_toml(content: &str) -> Result<Self, anyhow::Error> {
+        let table: toml::Table = content
+            .parse()
+            .with_context(|| format!("failed to parse {CONFIG_FILE}"))?;
+
+        let mut settings = BTreeMap::new();
+        for (key, value) in table {
+            match value {
+                toml::Value::Table(table) => {
+                    for (name, value) in table {
+                        settings.insert((Some(key.clone()), normalize(&name)), Value::Toml(value));
+                    }
+                }
+                value => {
+                    settings.insert((None, normalize(&key)), Value::Toml(value));
+                }
+            }
+        }
+
+        Ok(Config {
+            source: CONFIG_FILE.to_string(),
+            settings,
+        })
+    }
+
+    fn from_git(repository: &Repository) -> Result<Self, anyhow::Error> {
+        let config = repository.config()?;
+
+        let mut settings = BTreeMap::<_, Vec<String>>::new();
+        let mut entries = config.entries(Some("vergit\\..*"))?;
+        while let Some(entry) = entries.next() {
+            let entry = entry?;
+            let Some(name) = entry.name() else {
+                continue;
+            };
+
+            // Boolean options can be set without a value, meaning true.
+            let value = if entry.has_value() {
+                entry.value().unwrap_or_default()
+            } else {
+                ""
+            };
+
+            // Names are either 'vergit.<key>' or 'vergit.<subcommand>.<key>'.
+            let name = name.trim_start_matches("vergit.");
+            let key = match name.rsplit_once('.') {
+                Some((subcommand, key)) => (Some(subcommand.to_string()), normalize(key)),
+                None => (None, normalize(name)),
+            };
+
+            settings.entry(key).or_default().push(value.to_string());
+        }
+
+        Ok(Config {
+            source: String::from("git config"),
+            settings: settings
+                .into_iter()
+                .map(|(key, values)| (key, Value::Git(values)))
+                .collect(),
+        })
+    }
+
+    /// Inserts the configured options into the command-line arguments, so they
+    /// apply as defaults which are overridden by options given explicitly.
+    ///
+    /// The matches are those of the command-line arguments alone, and are used
+    /// to leave out configured options which would be combined with or conflict
+    /// with the options given explicitly, rather than being overridden by them.
+    pub fn apply(
+        &self,
+        command: &Command,
+        matches: &ArgMatches,
+        args: &[OsString],
+    ) -> Result<Vec<OsString>, anyhow::Error> {
+        let (subcommand, sub_matches) = matches
+            .subcommand()
+            .with_context(|| "no subcommand given")?;
+        let sub = command
+            .find_subcommand(subcommand)
+            .with_context(|| format!("unknown subcommand '{subcommand}'"))?;
+
+        let mut root_args = Vec::new();
+        let mut sub_args = BTreeMap::new();
+
+        for ((scope, key), value) in &self.settings {
+            match scope {
+                Some(scope) => {
+                    if command.find_subcommand(scope).is_none() {
+                        bail!("unknown section [{scope}] in {}", self.source);
+                    }
+
+                    if scope != subcommand {
+                        continue;
+                    }
+
+                    let arg = find_arg(sub, key).with_context(|| {
+                        format!("unknown option '{key}' in [{scope}] of {}", self.source)
+                    })?;
+                    let arguments = self.arguments(arg, key, value)?;
+                    if !overridden(sub, sub_matches, arg) {
+                        sub_args.insert(key, arguments);
+                    }
+                }
+                None => {
+                    if let Some(arg) = find_arg(command, key) {
+                        let arguments = self.arguments(arg, key, value)?;
+                        if !overridden(command, matches, arg) {
+                            root_args.extend(arguments);
+                        }
+                    } else if let Some(arg) = find_arg(sub, key) {
+                        // Options within the section of the subcommand take precedence.
+                        let arguments = self.arguments(arg, key, value)?;
+                        if !overridden(sub, sub_matches, arg) {
+                            sub_args.entry(key).or_insert(arguments);
+                        }
+                    } else if !command
+                        .get_subcommands()
+                        .any(|command| find_arg(command, key).is_some())
+                    {
+                        bail!("unknown option '{key}' in {}", self.source);
+                    }
+                }
+            }
+        }
+
+        let position = args
+            .iter()
+            .skip(1)
+            .position(|arg| arg == subcommand)
+            .map(|position| position + 2)
+            .unwrap_or(args.len());
+
+        let mut applied = args[..1].to_vec();
+        applied.extend(root_args.into_iter().map(OsString::from));
+        applied.extend_from_slice(&args[1..position]);
+        applied.extend(sub_args.into_values().flatten().map(OsString::from));
+        applied.extend_from_slice(&args[position..]);
+
+        Ok(applied)
+    }
+
+    /// Converts the configured value into command-line arguments for the option.
+    fn arguments(&self, arg: &Arg, key: &str, value: &Value) -> Result<Vec<String>, anyhow::Error> {
+        if let ArgAction::SetTrue = arg.get_action() {
+            let enabled = match value {
+                Value::Toml(toml::Value::Boolean(enabled)) => *enabled,
+                Value::Git(values) => values.last().is_some_and(|value| {
+                    matches!(
+                        value.to_lowercase().as_str(),
+                        "true" | "yes" | "on" | "1" | ""
+                    )
+                }),
+                Value::Toml(_) => bail!("'{key}' in {} must be true or false", self.source),
+            };
+
+            return Ok(if enabled {
+                vec![format!("--{key}")]
+            } else {
+                Vec::new()
+            });
+        }
+
+        let values = match value {
+            Value::Git(values) => values.clone(),
+            Value::Toml(toml::Value::Array(values)) => values
+                .iter()
+                .map(toml_string)
+                .collect::<Option<_>>()
+                .with_context(|| format!("'{key}' in {} must be a list of strings", self.source))?,
+            Value::Toml(value) => vec![toml_string(value)
+                .with_context(|| format!("'{key}' in {} must be a string", self.source))?],
+        };
+
+        Ok(values
+            .into_iter()
+            .map(|value| format!("--{key}={value}"))
+            .collect())
+    }
+}
+
+/// Adds a `--no-<flag>` option for every flag of the command and its
+/// subcommands, so flags enabled in the configuration can be disabled again
+/// on the command-line. Whichever of the two is given last takes effect.
+pub fn negatable(mut command: Command) -> Command {
+    let flags: Vec<_> = command
+        .get_arguments()
+        .filter(|arg| matches!(arg.get_action(), ArgAction::SetTrue))
+        .filter_map(|arg| Some((arg.get_id().to_string(), arg.get_long()?.to_string())))
+        .collect();
+
+    for (id, long) in flags {
+        let negation = format!("no-{long}");
+
+        command = command
+            .mut_arg(&id, |arg| arg.overrides_with(negation.clone()))
+            .arg(
+                Arg::new(negation.clone())
+                    .long(negation)
+                    .action(ArgAction::SetTrue)
+                    .overrides_with(id)
+                    .hide_short_help(true)
+                    .help(format!(
+                        "Disable --{long}, such as when it is enabled by default"
+                    )),
+            );
+    }
+
+    let subcommands: Vec<_> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+
+    for subcommand in subcommands {
+        command = command.mut_subcommand(subcommand, negatable);
+    }
+
+    command
+}
+
+/// Options are written like their long command-line flag, but underscores
+/// are accepted in place of dashes.
+fn normalize(key: &str) -> String {
+    key.replace('_', "-").to_lowercase()
+}
+
+/// Whether the configured value of the option is overridden by the options
+/// given on the command-line, which is the case if the option itself is given
+/// and accepts multiple values, which would otherwise be combined with the
+/// configured ones, or if an option conflicting with it is given.
+fn overridden(command: &Command, matches: &ArgMatches, arg: &Arg) -> bool {
+    let given =
+        |arg: &Arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine);
+
+    if matches!(arg.get_action(), ArgAction::Append) && given(arg) {
+        return true;
+    }
+
+    command
+        .get_arguments()
+        .filter(|other| given(other))
+        .any(|other| {
+            command.get_arg_conflicts_with(arg).contains(&other)
+                || command.get_arg_conflicts_with(other).contains(&arg)
+        })
+}
+
+/// Finds the option of the command with the given long name.
+fn find_arg<'a>(command: &'a Command, key: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .find(|arg| arg.get_long() == Some(key))
+}
+
+fn toml_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{CommandFactory, FromArgMatches};
+    use indoc::indoc;
+
+    use super::*;
+    use crate::{BumpCommand, Commands, Opts};
+
+    /// Parses the arguments of bump with the configuration applied.
+    fn parse(config: &Config, args: &[&str]) -> Result<BumpCommand, anyhow::Error> {
+        let command = negatable(Opts::command());
+        let args: Vec<OsString> = ["vergit", "bump"]
+            .iter()
+            .chain(args)
+            .map(OsString::from)
+            .collect();
+
+        let matches = command.clone().try_get_matches_from(&args)?;
+        let args = config.apply(&command, &matches, &args)?;
+
+        match Opts::from_arg_matches(&command.try_get_matches_from(args)?)?.subcommand {
+            Commands::Bump(bump) => Ok(bump),
+            _ => unreachable!("bump was parsed"),
+        }
+    }
+
+    fn bump(config: &str, args: &[&str]) -> Result<BumpCommand, anyhow::Error> {
+        parse(&Config::from_toml(config)?, args)
+    }
+
+    #[test]
+    fn command_sections_take_precedence() {
+        let config = "remote = \"global\"\n[bump]\nremote = \"bump\"\n";
+        assert_eq!(bump(config, &[]).unwrap().release.remote, "bump");
+
+        let config = "remote = \"global\"\n[changelog]\nfile = \"CHANGES.md\"\n";
+        assert_eq!(bump(config, &[]).unwrap().release.remote, "global");
+    }
+
+    #[test]
+    fn command_line_takes_precedence() {
+        let bump = bump("[bump]\nremote = \"bump\"\n", &["--remote", "cli"]).unwrap();
+        assert_eq!(bump.release.remote, "cli");
+    }
+
+    #[test]
+    fn rejects_unknown_options() {
+        assert!(bump("[bump]\nunknown = true\n", &[]).is_err());
+        assert!(bump("unknown = true\n", &[]).is_err());
+        assert!(bump("[unknown]\npush = true\n", &[]).is_err());
+
+        // Top-level options only need to be known by any of the commands.
+        assert!(bump("exclude-prereleases = true\n", &[]).is_ok());
+    }
+
+    #[test]
+    fn applies_booleans() {
+        assert!(bump("[bump]\npush = true\n", &[]).unwrap().release.push);
+        assert!(!bump("[bump]\npush = false\n", &[]).unwrap().release.push);
+        assert!(bump("[bump]\npush = \"yes\"\n", &[]).is_err());
+    }
+
+    #[test]
+    fn negates_flags() {
+        let bump = bump("[bump]\npush = true\nallow_dirty = true\n", &["--no-push"]).unwrap();
+        assert!(!bump.release.push);
+        assert!(bump.release.allow_dirty);
+
+        // Whichever of a flag and its negation is given last takes effect.
+        let config = Config::from_toml("").unwrap();
+        assert!(
+            !parse(&config, &["--push", "--no-push"])
+                .unwrap()
+                .release
+                .push
+        );
+        assert!(
+            parse(&config, &["--no-push", "--push"])
+                .unwrap()
+                .release
+                .push
+        );
+    }
+
+    #[test]
+    fn replaces_lists_given_on_command_line() {
+        let config = "[bump]\nversion-file = [\"A:{current}\", \"B:{current}\"]\n";
+        let paths = |bump: BumpCommand| -> Vec<_> {
+            bump.release
+                .version_files
+                .iter()
+                .map(|file| file.path.display().to_string())
+                .collect()
+        };
+
+        assert_eq!(paths(bump(config, &[]).unwrap()), ["A", "B"]);
+        assert_eq!(
+            paths(bump(config, &["--version-file", "C:{current}"]).unwrap()),
+            ["C"]
+        );
+    }
+
+    #[test]
+    fn drops_options_conflicting_with_command_line() {
+        let bump_with = |config| bump(config, &["--message", "hi"]).unwrap();
+
+        let bump = bump_with("[bump]\nlightweight = true\n");
+        assert!(!bump.release.lightweight);
+        assert_eq!(bump.release.message.as_deref(), Some("hi"));
+
+        let bump = bump_with("[bump]\nmessage-file = \"NOTES\"\n");
+        assert_eq!(bump.release.message.as_deref(), Some("hi"));
+        assert!(bump.release.message_file.is_none());
+    }
+
+    #[test]
+    fn reads_git_config() {
+        let directory = tempfile::tempdir().unwrap();
+        let repository = Repository::init(directory.path()).unwrap();
+
+        let path = directory.path().join(".git").join("config");
+        let mut content = fs::read_to_string(&path).unwrap();
+        content.push_str(indoc! {r#"
+            [vergit]
+                prefix = v
+            [vergit "bump"]
+                push
+                allow-dirty = no
+                version-file = A:{current}
+                version-file = B:{current}
+        "#});
+        fs::write(&path, content).unwrap();
+
+        let bump = parse(&Config::from_git(&repository).unwrap(), &[]).unwrap();
+        assert_eq!(bump.release.repository.prefix.as_deref(), Some("v"));
+        assert!(bump.release.push);
+        assert!(!bump.release.allow_dirty);
+        assert_eq!(bump.release.version_files.len(), 2);
+    }
+}
@@ -0,0 +1,144 @@
+use git2::Commit;
+
+use crate::bump::Component;
+
+/// A commit message following the Conventional Commits specification, for
+/// example: 'feat(parser)!: support arrays'
+pub struct ConventionalCommit {
+    /// Type of the commit, such as 'feat' or 'fix', always in lowercase.
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl ConventionalCommit {
+    /// Parses the header and footers of a commit message, returning None if the
+    /// header does not follow the Conventional Commits specification.
+    pub fn parse(message: &str) -> Option<Self> {
+        let mut lines = message.lines();
+        let (prefix, description) = lines.next()?.trim().split_once(": ")?;
+
+        let (prefix, breaking) = match prefix.strip_suffix('!') {
+            Some(prefix) => (prefix, true),
+            None => (prefix, false),
+        };
+
+        let (kind, scope) = match prefix.split_once('(') {
+            Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.to_string())),
+            None => (prefix, None),
+        };
+
+        if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            return None;
+        }
+
+        let breaking = breaking
+            || lines.any(|line| {
+                line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+            });
+
+        Some(ConventionalCommit {
+            kind: kind.to_lowercase(),
+            scope,
+            breaking,
+            description: description.trim().to_string(),
+        })
+    }
+
+    /// The component which has to be bumped to release this change, if any.
+    ///
+    /// Breaking changes require a major bump, features a minor bump and fixes
+    /// or performance improvements a patch bump. Any other type of change, such
+    /// as 'chore' or 'docs', does not require a release on its own.
+    pub fn component(&self) -> Option<Component> {
+        if self.breaking {
+            return Some(Component::Major);
+        }
+
+        match self.kind.as_str() {
+            "feat" => Some(Component::Minor),
+            "fix" | "perf" => Some(Component::Patch),
+            _ => None,
+        }
+    }
+}
+
+/// Picks the largest component required by any of the commits, returning None
+/// if none of them contain releasable changes. Commits which do not follow the
+/// Conventional Commits specification are ignored.
+pub fn infer_component(commits: &[Commit]) -> Option<Component> {
+    let components: Vec<_> = commits
+        .iter()
+        .filter_map(|commit| commit.message().and_then(ConventionalCommit::parse))
+        .filter_map(|commit| commit.component())
+        .collect();
+
+    [Component::Major, Component::Minor, Component::Patch]
+        .iter()
+        .find(|component| components.contains(component))
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_header() {
+        let commit = ConventionalCommit::parse("feat(parser): support arrays\n").unwrap();
+        assert_eq!(commit.kind, "feat");
+        assert_eq!(commit.scope.as_deref(), Some("parser"));
+        assert!(!commit.breaking);
+        assert_eq!(commit.description, "support arrays");
+
+        let commit = ConventionalCommit::parse("Fix: handle empty input").unwrap();
+        assert_eq!(commit.kind, "fix");
+        assert_eq!(commit.scope, None);
+    }
+
+    #[test]
+    fn rejects_other_messages() {
+        for message in [
+            "Update README",
+            "feat(parser: missing paren",
+            ": no type",
+            "a b: c",
+        ] {
+            assert!(ConventionalCommit::parse(message).is_none(), "{}", message);
+        }
+    }
+
+    #[test]
+    fn detects_breaking_changes() {
+        assert!(
+            ConventionalCommit::parse("feat!: drop support")
+                .unwrap()
+                .breaking
+        );
+        assert!(
+            ConventionalCommit::parse("fix(api)!: rename")
+                .unwrap()
+                .breaking
+        );
+
+        let commit =
+            ConventionalCommit::parse("fix: rename\n\nBREAKING CHANGE: renamed `a` to `b`")
+                .unwrap();
+        assert!(commit.breaking);
+        assert!(matches!(commit.component(), Some(Component::Major)));
+
+        let commit = ConventionalCommit::parse("fix: rename\n\nBREAKING-CHANGE: yes").unwrap();
+        assert!(commit.breaking);
+    }
+
+    #[test]
+    fn maps_types_to_components() {
+        let component = |message| ConventionalCommit::parse(message).unwrap().component();
+        assert!(matches!(component("feat: a"), Some(Component::Minor)));
+        assert!(matches!(component("fix: a"), Some(Component::Patch)));
+        assert!(matches!(component("perf: a"), Some(Component::Patch)));
+        assert!(component("chore: a").is_none());
+        assert!(component("docs: a").is_none());
+    }
+}
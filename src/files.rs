@@ -0,0 +1,328 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use regex::{Captures, Regex};
+use semver::Version;
+use similar::TextDiff;
+
+/// Pending modifications of files within a working directory, which can be
+/// written to disk or rendered as a unified diff.
+pub struct Changes {
+    workdir: PathBuf,
+    /// Original and modified contents of each file, keyed by their path
+    /// relative to the working directory. Files which did not exist yet have
+    /// no original contents.
+    files: BTreeMap<PathBuf, (Option<String>, String)>,
+}
+
+impl Changes {
+    pub fn new(workdir: &Path) -> Self {
+        Changes {
+            workdir: workdir.to_path_buf(),
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the pending contents of the file, which is relative to the
+    /// working directory unless absolute. Files which do not exist are empty.
+    pub fn content(&mut self, path: &Path) -> Result<&mut String, anyhow::Error> {
+        let path = path.strip_prefix(&self.workdir).unwrap_or(path);
+
+        if !self.files.contains_key(path) {
+            let absolute = self.workdir.join(path);
+            let original = match fs::read_to_string(&absolute) {
+                Ok(content) => Some(content),
+                Err(error) if error.kind() == ErrorKind::NotFound => None,
+                Err(error) => {
+                    return Err(error)
+                        .with_context(|| format!("failed to read {}", absolute.display()))
+                }
+            };
+
+            let content = original.clone().unwrap_or_default();
+            self.files.insert(path.to_path_buf(), (original, content));
+        }
+
+        Ok(&mut self.files.get_mut(path).expect("file was just inserted").1)
+    }
+
+    /// Iterates over the files whose contents were actually modified.
+    fn modified(&self) -> impl Iterator<Item = (&PathBuf, &Option<String>, &String)> {
+        self.files
+            .iter()
+            .filter(|(_, (original, content))| original.as_ref() != Some(content))
+            .map(|(path, (original, content))| (path, original, content))
+    }
+
+    /// Paths of the modified files, relative to the working directory.
+    pub fn paths(&self) -> Vec<&PathBuf> {
+        self.modified().map(|(path, _, _)| path).collect()
+    }
+
+    /// Renders the modifications as a unified diff.
+    pub fn diff(&self) -> String {
+        let mut diff = String::new();
+
+        for (path, original, content) in self.modified() {
+            let old_name = match original {
+                Some(_) => format!("a/{}", path.display()),
+                None => String::from("/dev/null"),
+            };
+
+            diff.push_str(
+                &TextDiff::from_lines(original.as_deref().unwrap_or_default(), content)
+                    .unified_diff()
+                    .header(&old_name, &format!("b/{}", path.display()))
+                    .to_string(),
+            );
+        }
+
+        diff
+    }
+
+    /// Writes the modified files to disk.
+    pub fn write(&self) -> Result<(), anyhow::Error> {
+        for (path, _, content) in self.modified() {
+            let path = self.workdir.join(path);
+            fs::write(&path, content)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Restores the original contents of the modified files on disk, removing
+    /// the files which did not exist before.
+    pub fn restore(&self) -> Result<(), anyhow::Error> {
+        for (path, original, _) in self.modified() {
+            let path = self.workdir.join(path);
+            match original {
+                Some(original) => fs::write(&path, original),
+                None => fs::remove_file(&path),
+            }
+            .with_context(|| format!("failed to restore {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A pattern matching the current version within a file, along with how to
+/// replace it when bumping the version.
+///
+/// Written as `<path>:<search>` or `<path>:<search>=><replace>`, where search
+/// is a regular expression in which `{current}` matches the current version.
+/// If no replacement is given, only the current version within each match is
+/// replaced by the new one. Otherwise each match is replaced entirely, with
+/// `{current}` and `{new}` substituted by the versions, and `$1` or `${name}`
+/// by the groups captured by the search.
+#[derive(Clone)]
+pub struct VersionFile {
+    pub path: PathBuf,
+    pub search: String,
+    pub replace: Option<String>,
+}
+
+impl FromStr for VersionFile {
+    type Err = anyhow::Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (path, pattern) = spec
+            .split_once(':')
+            .with_context(|| "version files must be written as <path>:<search>[=><replace>]")?;
+
+        let (search, replace) = match pattern.split_once("=>") {
+            Some((search, replace)) => (search, Some(replace.to_string())),
+            None => (pattern, None),
+        };
+
+        if replace.is_none() && !search.contains("{current}") {
+            bail!(
+                "search pattern '{search}' must contain {{current}} unless a replacement is given"
+            );
+        }
+
+        Ok(VersionFile {
+            path: PathBuf::from(path),
+            search: search.to_string(),
+            replace,
+        })
+    }
+}
+
+impl VersionFile {
+    /// Replaces the current version with the new version within the file,
+    /// failing if the search pattern does not match anything.
+    pub fn apply(
+        &self,
+        changes: &mut Changes,
+        current: &Version,
+        new: &Version,
+    ) -> Result<(), anyhow::Error> {
+        let (current, new) = (current.to_string(), new.to_string());
+
+        // Only the first occurrence of {current} is captured, as the names of
+        // capture groups have to be unique.
+        let search = Regex::new(
+            &self
+                .search
+                .replacen(
+                    "{current}",
+                    &format!("(?P<current>{})", regex::escape(&current)),
+                    1,
+                )
+                .replace("{current}", &regex::escape(&current)),
+        )
+        .with_context(|| format!("invalid search pattern '{}'", self.search))?;
+
+        let content = changes.content(&self.path)?;
+        if !search.is_match(content) {
+            bail!(
+                "search pattern '{}' did not match anything in {}",
+                self.search,
+                self.path.display()
+            );
+        }
+
+        let replaced = search
+            .replace_all(content, |captures: &Captures| match &self.replace {
+                Some(replace) => {
+                    let mut replaced = String::new();
+                    captures.expand(
+                        &replace
+                            .replace("{current}", &current)
+                            .replace("{new}", &new),
+                        &mut replaced,
+                    );
+                    replaced
+                }
+                None => {
+                    let whole = captures.get(0).expect("the whole match is always captured");
+                    let matched = whole.as_str();
+
+                    // {current} might be within an optional group, which did not
+                    // take part in this match, leaving nothing to replace.
+                    let Some(version) = captures.name("current") else {
+                        return matched.to_string();
+                    };
+
+                    format!(
+                        "{}{new}{}",
+                        &matched[..version.start() - whole.start()],
+                        &matched[version.end() - whole.start()..]
+                    )
+                }
+            })
+            .into_owned();
+
+        *content = replaced;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies the version file to the given contents, without touching disk.
+    fn apply(spec: &str, content: &str) -> Result<String, anyhow::Error> {
+        let version_file = VersionFile::from_str(spec)?;
+        let mut changes = Changes::new(Path::new("/nonexistent"));
+        *changes.content(&version_file.path)? = content.to_string();
+
+        let current = Version::parse("1.2.3")?;
+        let new = Version::parse("1.3.0")?;
+        version_file.apply(&mut changes, &current, &new)?;
+
+        Ok(changes.content(&version_file.path)?.clone())
+    }
+
+    #[test]
+    fn replaces_current_version_within_matches() {
+        assert_eq!(
+            apply(
+                r"README.md:\?ref={current}",
+                "uses: a?ref=1.2.3\nkeeps 1.2.3\n"
+            )
+            .unwrap(),
+            "uses: a?ref=1.3.0\nkeeps 1.2.3\n"
+        );
+    }
+
+    #[test]
+    fn replaces_matches_with_captures() {
+        assert_eq!(
+            apply(
+                r#"Dockerfile:(?P<key>LABEL version)="(.*)"=>${key}="{new}" # was {current}"#,
+                "LABEL version=\"1.2.3\"\n"
+            )
+            .unwrap(),
+            "LABEL version=\"1.3.0\" # was 1.2.3\n"
+        );
+    }
+
+    #[test]
+    fn skips_matches_without_current_version() {
+        assert_eq!(
+            apply("F:x=(?:{current})?", "x=1.2.3\nx=\n").unwrap(),
+            "x=1.3.0\nx=\n"
+        );
+        assert_eq!(
+            apply("F:version (?:{current}|unknown)", "version unknown\n").unwrap(),
+            "version unknown\n"
+        );
+    }
+
+    #[test]
+    fn escapes_current_version() {
+        // The dots of the version must not match arbitrary characters.
+        assert!(apply("VERSION:^{current}$", "1x2x3").is_err());
+    }
+
+    #[test]
+    fn fails_if_nothing_matches() {
+        let error = apply("VERSION:version {current}", "version 1.0.0\n").unwrap_err();
+        assert!(error.to_string().contains("did not match anything"));
+    }
+
+    #[test]
+    fn parses_specs() {
+        let version_file = VersionFile::from_str("a:b:{current}=>c").unwrap();
+        assert_eq!(version_file.path, PathBuf::from("a"));
+        assert_eq!(version_file.search, "b:{current}");
+        assert_eq!(version_file.replace.as_deref(), Some("c"));
+
+        assert!(VersionFile::from_str("no-separator").is_err());
+        assert!(VersionFile::from_str("VERSION:no placeholder").is_err());
+    }
+
+    #[test]
+    fn restores_written_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = |name| directory.path().join(name);
+        fs::write(path("CHANGELOG.md"), "# Changelog\n").unwrap();
+
+        let mut changes = Changes::new(directory.path());
+        changes
+            .content(Path::new("CHANGELOG.md"))
+            .unwrap()
+            .push_str("\n## [1.0.1]\n");
+        *changes.content(Path::new("VERSION")).unwrap() = String::from("1.0.1\n");
+
+        changes.write().unwrap();
+        assert!(path("VERSION").exists());
+
+        changes.restore().unwrap();
+        assert_eq!(
+            fs::read_to_string(path("CHANGELOG.md")).unwrap(),
+            "# Changelog\n"
+        );
+        assert!(!path("VERSION").exists());
+    }
+}
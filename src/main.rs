@@ -1,22 +1,80 @@
-use std::{path::PathBuf, str::FromStr, u64};
+use std::{collections::BTreeMap, fs, path::PathBuf};
 
-use anyhow::Context;
-use clap::{arg, Parser, Subcommand, ValueEnum};
-use git2::{Cred, CredentialType, ObjectType, PushOptions, RemoteCallbacks, Repository};
+use anyhow::{bail, Context};
+use clap::{Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use git2::{ObjectType, Repository};
 use indoc::indoc;
-use semver::Prerelease;
-
-#[derive(Clone, ValueEnum, Default)]
-enum Component {
-    Major,
-    Minor,
-    #[default]
-    Patch,
-    Prerelease,
+use semver::Version;
+
+mod bump;
+mod changelog;
+mod commit;
+mod config;
+mod conventional;
+mod files;
+mod manifest;
+mod output;
+mod remote;
+mod tags;
+#[cfg(test)]
+mod testing;
+
+use bump::{increment, Component};
+use commit::{commit_paths, uncommitted_changes, undo_commit};
+use config::Config;
+use conventional::infer_component;
+use files::{Changes, VersionFile};
+use output::{Output, Release};
+use tags::{
+    commits_since, head_versions, latest_version, reachable_from_head, version_tags, TagFormat,
+    VersionTag,
+};
+
+#[derive(Args)]
+struct RepositoryArgs {
+    #[arg(long, help = "Search all tags within the repository, not just the immediate history of this branch", long_help = indoc! {"
+        Instead of walking backwards in the currently checked out history to find a tag 
+        to increment, vergit will look at all tags in the entire repository and increment 
+        the highest absolute version it can find.
+    "})]
+    pub global: bool,
+
+    #[arg(
+        long,
+        help = "Path of the git repository [default: . (current working directory)]"
+    )]
+    pub path: Option<String>,
+
+    #[arg(long = "component", value_name = "NAME", help = "Only use version tags within the <NAME>/ namespace", long_help = indoc! {"
+        Versions each component of a monorepo independently, by only considering tags
+        named <NAME>/<version> as versions. Commands creating a version, such as bump
+        and set, create its tag within the same namespace.
+
+        For example, in a repository with the following tags:
+            networking/v1.4.0
+            storage/v2.0.1
+
+        The following command:
+            $ vergit bump minor --component networking
+
+        Will create a new tag networking/v1.5.0 pointing at HEAD
+    "})]
+    pub namespace: Option<String>,
+
+    #[arg(long, help = "Prefix of version tags, such as 'v' in 'v1.2.3'", long_help = indoc! {"
+        Prefix which version tags are expected to start with, such as 'v' for tags like
+        v1.2.3, or 'release-' for tags like release-1.2.3. The prefix is stripped when
+        looking for existing versions, and added to the tags of versions created by
+        commands such as bump and set.
+
+        If not specified, the prefix 'v' will be used if all existing version tags
+        in the repository consistently use it, otherwise no prefix is used.
+    "})]
+    pub prefix: Option<String>,
 }
 
-#[derive(Parser)]
-struct BumpCommand {
+#[derive(Args)]
+struct VersionArgs {
     #[arg(
         value_enum,
         help = "Defaults to 'prerelease' if version has prerelease, otherwise 'patch'",
@@ -38,32 +96,110 @@ struct BumpCommand {
                 0.0.1-beta.1    => 0.0.1-beta.2
                 0.0.1-alpha.3   => 0.0.1-alpha.4
                 0.0.1-test.b.2  => 0.0.1-test.b.3
+
+            Prereleases can be promoted to their final version using the release command
+            (or its alias finalize), which fails if the current version is not a prerelease:
+                2.0.0-rc.4      => 2.0.0
+
+            The auto command infers the component from the Conventional Commits made since
+            the latest version: breaking changes bump major, 'feat' bumps minor and 'fix'
+            or 'perf' bumps patch. It fails if no such commits are found, for example if
+            only 'chore' or 'docs' commits were made.
         "}
     )]
     pub component: Option<Component>,
 
+    #[arg(long, value_name = "LABEL", help = "Start or switch to a prerelease line with the given label", long_help = indoc! {"
+        Combined with major, minor or patch, starts a new prerelease line with the given
+        label. Combined with prerelease, switches to the given label if the current
+        prerelease uses a different one, and otherwise increments it as usual.
+
+        For example:
+            1.4.2          minor --pre rc         => 1.5.0-rc.1
+            1.5.0-alpha.3  prerelease --pre beta  => 1.5.0-beta.1
+            1.5.0-beta.1   prerelease --pre beta  => 1.5.0-beta.2
+    "})]
+    pub pre: Option<String>,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct BumpCommand {
+    #[command(flatten)]
+    pub version: VersionArgs,
+
+    #[command(flatten)]
+    pub release: ReleaseArgs,
+}
+
+/// Options controlling how a new version is tagged, committed and pushed,
+/// shared by the commands which create versions.
+#[derive(Args)]
+struct ReleaseArgs {
+    #[command(flatten)]
+    pub repository: RepositoryArgs,
+
     #[arg(long, help = "Push the new tag to a remote repository immediately", long_help = indoc!{"
         The newly created tag will be pushed to a remote repository.
 
         The remote to push to can be overridden with --remote and defaults to 'origin'.
+
+        Before anything is tagged, the tags of the remote are listed and taken into
+        account when looking for the latest version, even if they were not fetched yet.
+        Unless --global is used, only remote tags pointing at commits in the history of
+        HEAD are considered, like local tags. If the new tag already exists on the
+        remote, vergit aborts without tagging.
+
+        HTTPS remotes are authenticated using, in order:
+            - The token in $VERGIT_TOKEN, with the username in $VERGIT_USERNAME, or the
+              username of the remote url, or 'x-access-token'
+            - The $GIT_ASKPASS program
+            - The credential helpers configured for git
+
+        SSH remotes are authenticated using, in order:
+            - The key given with --ssh-key
+            - The keys of the running ssh-agent
+            - The default keys ~/.ssh/id_ed25519 and ~/.ssh/id_rsa
+        Encrypted keys are decrypted using the passphrase in $VERGIT_SSH_PASSPHRASE.
     "})]
     pub push: bool,
 
-    #[arg(long, help = "Search all tags within the repository, not just the immediate history of this branch", long_help = indoc! {"
-        Instead of walking backwards in the currently checked out history to find a tag 
-        to increment, vergit will look at all tags in the entire repository and increment 
-        the highest absolute version it can find.
-    "})]
-    pub global: bool,
+    #[arg(long, default_value = "origin", help = "Set the remote to push to")]
+    pub remote: String,
 
     #[arg(
         long,
-        help = "Path of the git repository to bump [default: . (current working directory)]"
+        value_name = "PATH",
+        help = "Private key to authenticate with when pushing to SSH remotes"
     )]
-    pub path: Option<String>,
+    pub ssh_key: Option<PathBuf>,
 
-    #[arg(long, default_value = "origin", help = "Set the remote to push to")]
-    pub remote: String,
+    #[arg(long, help = "Keep the new tag if pushing it fails", long_help = indoc! {"
+        By default, the newly created tag is deleted again if it could not be pushed, so
+        the next attempt creates the same version instead of skipping past it. A release
+        commit made for --manifests or --version-file is undone as well, and the files
+        changed by vergit, such as the changelog, are restored. With --keep-on-failure,
+        the tag and release commit are kept locally and have to be pushed by hand.
+    "})]
+    pub keep_on_failure: bool,
+
+    #[arg(long, help = "Allow bumping when tracked files have uncommitted changes", long_help = indoc! {"
+        By default, bump refuses to create a tag if any tracked files have uncommitted
+        changes, since the tagged commit would not match the working directory. This
+        check is skipped with --allow-dirty. In dry-run mode, a warning is printed to
+        stderr instead.
+    "})]
+    pub allow_dirty: bool,
+
+    #[arg(long, help = "Print the existing version instead of failing if HEAD is already tagged", long_help = indoc! {"
+        By default, bump refuses to create a tag if HEAD is already tagged with a version,
+        so running it twice on the same commit doesn't create two versions. With
+        --idempotent, the existing tag is printed and vergit exits successfully instead,
+        which makes it safe to retry failed CI jobs.
+
+        For set, only a tag with the requested version counts as the existing tag.
+    "})]
+    pub idempotent: bool,
 
     #[arg(long, help = "Create no tags, just print the updated tag", long_help = indoc! {"
         In dry-run mode, no changes will be made to the git repository at all, the
@@ -75,9 +211,242 @@ struct BumpCommand {
         Will yield the following output to stdout:
             0.0.2
         
-        But make no modifications to the git repository
+        But make no modifications to the git repository. Changes that would otherwise
+        be made to files, such as with --manifests or --version-file, are printed to
+        stderr as a unified diff.
     "})]
     pub dry_run: bool,
+
+    #[arg(long, value_enum, default_value_t, value_name = "FORMAT", help = "Set the format of the output", long_help = indoc! {"
+        Set the format of the output printed to stdout.
+
+        With 'json', an object with the previous version (null if there was none), the
+        new version, the bumped component, the id of the tagged commit, the name of the
+        tag, and whether it was pushed is printed, for example:
+            {
+              \"previous_version\": \"1.4.1\",
+              \"version\": \"1.5.0\",
+              \"component\": \"minor\",
+              \"commit\": \"3f2a9c1e...\",
+              \"tag\": \"v1.5.0\",
+              \"pushed\": true
+            }
+    "})]
+    pub output: Output,
+
+    #[arg(long, value_name = "FILE", help = "Append the new version to a file as environment variables", long_help = indoc! {"
+        Append the new version to the file in dotenv format, as the variables
+        VERGIT_PREVIOUS, VERGIT_VERSION, VERGIT_MAJOR, VERGIT_MINOR, VERGIT_PATCH,
+        VERGIT_PRERELEASE and VERGIT_TAG.
+
+        This is compatible with both the outputs of GitHub Actions and the dotenv
+        artifacts of GitLab CI, for example:
+            $ vergit bump --output-env \"$GITHUB_OUTPUT\"
+    "})]
+    pub output_env: Option<PathBuf>,
+
+    #[arg(long, value_name = "FILE", help = "Prepend the changes since the latest version to a changelog", long_help = indoc! {"
+        Prepends a section for the new version to the given changelog file, listing the
+        Conventional Commits made since the latest version grouped by their type. See
+        the changelog command for details.
+
+        The changelog is written before the tag is created, but is only committed if
+        --manifests or --version-file is used, as part of the release commit.
+    "})]
+    pub changelog: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        help = "Use the given message as the annotation of the new tag"
+    )]
+    pub message: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "message",
+        help = "Read the annotation of the new tag from the given file"
+    )]
+    pub message_file: Option<PathBuf>,
+
+    #[arg(long, help = "List the commits since the latest version in the annotation of the new tag", long_help = indoc! {"
+        Adds the subject of every commit made since the latest version to the annotation
+        of the new tag, so the changes are shown by 'git show <tag>'. If a message is
+        also given using --message or --message-file, the list follows the message.
+
+        For example:
+            Changes since 1.3.0:
+
+            - feat: support arrays
+            - fix: handle empty input
+    "})]
+    pub notes: bool,
+
+    #[arg(long, conflicts_with_all = ["message", "message_file", "notes"], help = "Create a lightweight tag instead of an annotated one", long_help = indoc! {"
+        Creates a lightweight tag, which is a plain reference to the commit, instead of
+        an annotated tag object carrying a tagger, date and message. Some tools treat the
+        two kinds of tags differently.
+
+        Both kinds of tags are always considered when looking for the latest version.
+    "})]
+    pub lightweight: bool,
+
+    #[arg(long, help = "Update the version field of manifest files, and commit them before tagging", long_help = indoc! {"
+        Sets the version field of the manifests at the root of the repository to the new
+        version, then commits them and tags the new commit instead of the original HEAD.
+        If --changelog is used as well, the changelog is included in the same commit.
+
+        The following manifests are updated:
+            Cargo.toml      [package] or [workspace.package], along with the members of
+                            the workspace and their entries in Cargo.lock
+            package.json    top-level \"version\"
+            pyproject.toml  [project] or [tool.poetry]
+            Chart.yaml      top-level version
+
+        With --push, the current branch is pushed along with the tag.
+    "})]
+    pub manifests: bool,
+
+    #[arg(long = "version-file", value_name = "PATH:SEARCH[=>REPLACE]", help = "Replace the version within a file using a regular expression, and commit it before tagging", long_help = indoc! {"
+        Replaces the current version within the file, relative to the root of the
+        repository, by the new version. The file is then committed along with any other
+        updated files, and the new commit is tagged instead of the original HEAD.
+
+        SEARCH is a regular expression in which {current} matches the current version.
+        Without a REPLACE, only the current version within each match is replaced. With
+        it, each match is replaced by REPLACE, in which {current} and {new} are replaced
+        by the versions, and $1 or ${name} by the groups captured by SEARCH.
+
+        Fails if SEARCH does not match anything. Can be specified multiple times.
+
+        For example:
+            --version-file 'README.md:\\?ref={current}'
+            --version-file 'Dockerfile:LABEL version=\"(.*)\"=>LABEL version=\"{new}\"'
+    "})]
+    pub version_files: Vec<VersionFile>,
+
+    #[arg(
+        long,
+        value_name = "MESSAGE",
+        default_value = "chore(release): {version}",
+        help = "Message of the release commit, where {version} and {tag} are replaced"
+    )]
+    pub commit_message: String,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct ChangelogCommand {
+    #[command(flatten)]
+    pub version: VersionArgs,
+
+    #[command(flatten)]
+    pub repository: RepositoryArgs,
+
+    #[arg(
+        long,
+        default_value = "CHANGELOG.md",
+        help = "Changelog file to prepend the new section to, relative to the repository root"
+    )]
+    pub file: PathBuf,
+
+    #[arg(
+        long,
+        help = "Print the new section instead of writing it to the changelog"
+    )]
+    pub dry_run: bool,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct CurrentCommand {
+    #[command(flatten)]
+    pub repository: RepositoryArgs,
+}
+
+/// Additional columns printed by the list command.
+#[derive(Clone, ValueEnum)]
+enum Column {
+    /// Date of the tagged commit
+    Date,
+    /// Name of the tagger, or '-' for lightweight tags
+    Tagger,
+    /// Abbreviated id of the tagged commit
+    Sha,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct ListCommand {
+    #[command(flatten)]
+    pub repository: RepositoryArgs,
+
+    #[arg(
+        long,
+        conflicts_with = "global",
+        help = "Only list versions reachable from HEAD"
+    )]
+    pub reachable: bool,
+
+    #[arg(long, help = "Leave out prerelease versions")]
+    pub exclude_prereleases: bool,
+
+    #[arg(
+        long,
+        value_name = "MAJOR",
+        help = "Only list versions of the given major version"
+    )]
+    pub major: Option<u64>,
+
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        value_name = "COLUMNS",
+        help = "Comma-separated columns to print after the name of each tag"
+    )]
+    pub columns: Vec<Column>,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct DescribeCommand {
+    #[command(flatten)]
+    pub repository: RepositoryArgs,
+
+    #[arg(long, value_name = "FORMAT", default_value = "{next}{pre}dev.{distance}+g{sha}{dirty}", help = "Format of the version of untagged commits", long_help = indoc! {"
+        Format of the version printed for commits which are not tagged, in which the
+        following placeholders are replaced:
+            {version}   The latest version
+            {next}      The next patch version, or the release of the latest prerelease
+            {pre}       '-' followed by the prerelease of the latest version and a '.' if
+                        it has one, otherwise just '-'
+            {distance}  The number of commits since the latest version
+            {sha}       The abbreviated id of HEAD
+            {dirty}     '.dirty' if tracked files have uncommitted changes, otherwise empty
+
+        With the default format, development versions following a prerelease continue
+        its prerelease, so they are ordered after it but before the next prerelease:
+            1.5.0-rc.1  =>  1.5.0-rc.1.dev.3+g3f2a9c1
+    "})]
+    pub format: String,
+}
+
+#[derive(Parser)]
+#[command(args_override_self = true)]
+struct SetCommand {
+    #[arg(help = "The version to create, such as 3.0.0 or 3.0.0-rc.1")]
+    pub version: Version,
+
+    #[arg(
+        long,
+        help = "Allow creating a version lower than or equal to the latest version, or tagging an already tagged HEAD"
+    )]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub release: ReleaseArgs,
 }
 
 #[derive(Subcommand)]
@@ -103,9 +472,91 @@ enum Commands {
         "}
     )]
     Bump(BumpCommand),
+
+    #[command(
+        about = "Prepend the changes since the latest version tag to a changelog",
+        long_about = indoc! {"
+            Collects the commits made since the latest version tag, groups them by their
+            Conventional Commit type and prepends them to the changelog as a section for
+            the version that would be created by running bump with the same arguments.
+
+            Sections follow the Keep a Changelog format, for example:
+                ## [1.9.0] - 2023-02-14
+
+                ### Added
+                - **parser:** support arrays (3f2a9c1)
+
+                ### Fixed
+                - handle empty input (9b1c0de)
+
+            Breaking changes are listed first, followed by features (Added), fixes (Fixed),
+            refactors, performance improvements and reverts (Changed) and documentation.
+            Other commits, including those not following Conventional Commits, are omitted.
+        "}
+    )]
+    Changelog(ChangelogCommand),
+
+    #[command(
+        about = "Print the latest version tag and the commit it points at",
+        long_about = indoc! {"
+            Looks up the latest version tag the same way bump does, and prints its name
+            along with the id of the commit it points at, for example:
+                v1.8.5 3f2a9c1e0b6d4c8a7f5e2d1b9c0a8f7e6d5c4b3a
+
+            Exits with a non-zero status if no version tag is found.
+        "}
+    )]
+    Current(CurrentCommand),
+
+    #[command(
+        about = "List the version tags ordered by semantic-versioning precedence",
+        long_about = indoc! {"
+            Lists every version tag in the repository from lowest to highest, ordered by
+            semantic-versioning precedence rather than ASCIIbetically like git tag, so
+            0.0.9 is listed before 0.0.10. With --reachable, only the version tags in the
+            history of HEAD are listed, which are those bump considers without --global.
+
+            For example:
+                $ vergit list --major 1 --columns date,sha
+                v1.0.0  2023-01-09  9b1c0de
+                v1.1.0  2023-02-14  3f2a9c1
+        "}
+    )]
+    List(ListCommand),
+
+    #[command(
+        about = "Describe HEAD as a development version derived from the latest version tag",
+        long_about = indoc! {"
+            Like git describe, but finds the latest version tag by semantic-versioning
+            precedence, and prints a development version for HEAD derived from it, such as:
+                1.4.1-dev.7+g3f2a9c1
+
+            where 1.4.1 is the patch version following the latest version tag 1.4.0, and
+            HEAD is 7 commits ahead of that tag at commit 3f2a9c1. If HEAD is tagged and
+            the working directory is clean, the version of the tag is printed instead.
+        "}
+    )]
+    Describe(DescribeCommand),
+
+    #[command(
+        about = "Tag HEAD with the given version",
+        long_about = indoc! {"
+            Creates a tag for the given version instead of incrementing the latest one,
+            for example to jump to a new major version:
+                $ vergit set 3.0.0 --push
+
+            Refuses to create a version lower than or equal to the latest version, or to
+            tag HEAD if it is already tagged with a different version, unless --force is
+            given. Otherwise the version is tagged, committed, pushed and printed the same
+            way as by bump. If there are no version tags yet, the given version is created
+            as the first one.
+        "}
+    )]
+    Set(SetCommand),
 }
 
 #[derive(Parser)]
+#[command(args_override_self = true)]
 #[clap(long_about = indoc! {"
     Command-line utility for quickly incrementing and pushing semantic-versioning
     tags in a git repository.
@@ -128,6 +579,22 @@ enum Commands {
 
         Calculate the incremented tag and output it, but do not create the tag.
             $ vergit bump prerelease --dry-run
+
+    Configuration:
+        Default options can be set in a .vergit.toml file at the root of the repository,
+        using the names of the long command-line options. Top-level options apply to all
+        commands accepting them, while options within a [<command>] section only apply
+        to that command. Options given on the command-line take precedence, and flags
+        enabled in the configuration can be disabled again using --no-<flag>.
+            prefix = \"v\"
+
+            [bump]
+            push = true
+            remote = \"upstream\"
+
+        If the file does not exist, options are read from the [vergit] section of the
+        git config instead, with [vergit \"<command>\"] sections for each command.
+            $ git config vergit.bump.push true
 "})]
 struct Opts {
     #[command(subcommand)]
@@ -136,143 +603,639 @@ struct Opts {
     pub quiet: bool,
 }
 
-fn main() -> Result<(), anyhow::Error> {
-    let opts = Opts::parse();
+impl RepositoryArgs {
+    fn repository(&self) -> Result<Repository, anyhow::Error> {
+        let path = match &self.path {
+            Some(path) => Ok(PathBuf::from(path)),
+            None => std::env::current_dir(),
+        }?;
 
-    match &opts.subcommand {
-        Commands::Bump(bump) => {
-            let path = match &bump.path {
-                Some(path) => Ok(PathBuf::from(path)),
-                None => std::env::current_dir(),
-            }?;
-
-            let repository = Repository::open(path)?;
-
-            let all_versions: Vec<_> = repository
-                .tag_names(None)?
-                .into_iter()
-                .filter_map(Option::from)
-                .map(semver::Version::from_str)
-                .filter_map(Result::ok)
-                .collect();
+        Ok(Repository::open(path)?)
+    }
 
-            let latest_version = if bump.global {
-                all_versions.into_iter().max()
-            } else {
-                // Find all the tags which are pointing to the commit, pointed to by HEAD
-                // I previously used the git describe functionality for this, but if you
-                // had two tags pointing at the same commit for example, it would  only
-                // return one of the tags, which meant if you had two tags like for instance:
-                //      0.0.10
-                //      0.0.9
-                //
-                // pointing to the same commit, Describe would not order them correctly
-                // according to the semver spec, instead using plain ASCIIbetical ordering,
-                // meaning 0.0.9 would incorrectly be considered the latest version.
-                let head_commit = repository.head()?.peel_to_commit()?.id();
-                all_versions
-                    .into_iter()
-                    .filter(|v| {
-                        repository
-                            .refname_to_id(&v.to_string())
-                            .map_or(true, |tag_id| {
-                                repository
-                                    .find_tag(tag_id)
-                                    .map_or(true, |tag| tag.target_id() != head_commit)
-                            })
-                    })
-                    .max()
+    /// Opens the repository and determines the format of its version tags.
+    fn open(&self) -> Result<(Repository, TagFormat), anyhow::Error> {
+        let repository = self.repository()?;
+
+        let format = TagFormat::detect(
+            &repository,
+            self.namespace.as_deref(),
+            self.prefix.as_deref(),
+        )?;
+
+        Ok((repository, format))
+    }
+
+    /// Finds the latest version tag, failing if there is none.
+    fn latest(
+        &self,
+        repository: &Repository,
+        format: &TagFormat,
+    ) -> Result<VersionTag, anyhow::Error> {
+        latest_version(repository, format, self.global)?
+            .with_context(|| "No semantic versioning tags found")
+    }
+}
+
+impl VersionArgs {
+    /// Determines which component to bump, and the version produced by doing so.
+    fn next_version(
+        &self,
+        repository: &Repository,
+        latest: &VersionTag,
+    ) -> Result<(Component, Version), anyhow::Error> {
+        let field_to_bump = match &self.component {
+            Some(Component::Auto) => {
+                infer_component(&commits_since(repository, Some(latest.commit))?)
+                    .with_context(|| format!("no releasable changes since {}", latest.name))?
             }
-            .with_context(|| "No semantic versioning tags found")?;
-
-            let field_to_bump =
-                bump.component
-                    .as_ref()
-                    .unwrap_or(if !latest_version.pre.is_empty() {
-                        &Component::Prerelease
-                    } else {
-                        &Component::Patch
-                    });
-
-            let new_version = {
-                let mut new_version = latest_version;
-                match field_to_bump {
-                    Component::Major => {
-                        new_version.major += 1;
-                    }
-                    Component::Minor => {
-                        new_version.minor += 1;
-                    }
-                    Component::Patch => {
-                        new_version.patch += 1;
-                    }
-                    Component::Prerelease => {
-                        let prerelease = &new_version.pre;
+            Some(component) => component.clone(),
+            None if !latest.version.pre.is_empty() => Component::Prerelease,
+            None => Component::Patch,
+        };
 
-                        let (head, prerelease_version) =
-                            prerelease.rsplit_once('.').unwrap_or(("", prerelease));
+        let new_version = increment(&latest.version, &field_to_bump, self.pre.as_deref())?;
 
-                        let bumped_pre = prerelease_version.parse::<u64>().with_context(|| {
-                            "numeric part of prerelease is not a valid non-zero integer"
-                        })? + 1;
+        Ok((field_to_bump, new_version))
+    }
+}
 
-                        new_version.pre = if head.is_empty() {
-                            Prerelease::from_str(&format!("{bumped_pre}"))
-                        } else {
-                            Prerelease::from_str(&format!("{head}.{bumped_pre}"))
-                        }
-                        .with_context(|| "failed to rebuild prerelease tag after increment")?;
+impl ReleaseArgs {
+    /// Whether the modified files are committed before tagging, rather than
+    /// only the changelog being written.
+    fn commits_files(&self) -> bool {
+        self.manifests || !self.version_files.is_empty()
+    }
+
+    /// Builds the annotation of the new tag from the message and release notes.
+    fn tag_message(
+        &self,
+        repository: &Repository,
+        latest: Option<&VersionTag>,
+    ) -> Result<String, anyhow::Error> {
+        let mut message = match (&self.message, &self.message_file) {
+            (Some(message), _) => message.clone(),
+            (None, Some(file)) => fs::read_to_string(file)
+                .with_context(|| format!("failed to read message from {}", file.display()))?,
+            (None, None) => String::new(),
+        };
+
+        if self.notes {
+            if !message.is_empty() {
+                message = format!("{}\n\n", message.trim_end());
+            }
+
+            message.push_str(&changelog::notes(
+                latest,
+                &commits_since(repository, latest.map(|latest| latest.commit))?,
+            ));
+        }
+
+        Ok(message)
+    }
+
+    /// Prepares the changes to the changelog, manifests and version files, if
+    /// any of them are to be updated.
+    fn file_changes(
+        &self,
+        repository: &Repository,
+        latest: Option<&VersionTag>,
+        new_version: &Version,
+    ) -> Result<Option<Changes>, anyhow::Error> {
+        if self.changelog.is_none() && !self.manifests && self.version_files.is_empty() {
+            return Ok(None);
+        }
+
+        let workdir = repository
+            .workdir()
+            .with_context(|| "repository has no working directory")?;
+        let mut changes = Changes::new(workdir);
+
+        if let Some(changelog) = &self.changelog {
+            let section = changelog::section(
+                repository,
+                new_version,
+                &commits_since(repository, latest.map(|latest| latest.commit))?,
+            )?;
+
+            let content = changes.content(changelog)?;
+            *content = changelog::prepend(content, &section);
+        }
+
+        if self.manifests && manifest::update(&mut changes, repository, new_version)?.is_empty() {
+            bail!("no manifests with a version field found in the repository");
+        }
+
+        for version_file in &self.version_files {
+            let latest = latest.with_context(|| {
+                format!(
+                    "no current version to replace in {}",
+                    version_file.path.display()
+                )
+            })?;
+            version_file.apply(&mut changes, &latest.version, new_version)?;
+        }
+
+        Ok(Some(changes))
+    }
+
+    /// Prints the release, and writes it to the --output-env file if given.
+    fn report(&self, release: &Release, quiet: bool) -> Result<(), anyhow::Error> {
+        if let Some(path) = &self.output_env {
+            release.write_env(path)?;
+        }
+
+        if !quiet {
+            release.print(self.output)?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates the version determined from the latest version, if there is
+    /// one, by the given function, which also returns the component that was
+    /// bumped, if any.
+    ///
+    /// If a specific version is requested, HEAD only counts as already released
+    /// if it is tagged with that version. Tagging HEAD with a different version
+    /// fails, unless forced.
+    fn run<F>(
+        &self,
+        quiet: bool,
+        requested: Option<&Version>,
+        force: bool,
+        next_version: F,
+    ) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(
+            &Repository,
+            Option<&VersionTag>,
+        ) -> Result<(Option<Component>, Version), anyhow::Error>,
+    {
+        let (repository, format) = self.repository.open()?;
+        let mut latest = latest_version(&repository, &format, self.repository.global)?;
+
+        // Tags which were pushed by others but not fetched yet would make the
+        // new tag impossible to push, so the remote's tags are considered too.
+        let remote_tags = if self.push {
+            remote::tags(&repository, &self.remote, self.ssh_key.as_deref())?
+        } else {
+            BTreeMap::new()
+        };
+
+        // Outside of global mode, remote tags are only considered if they point
+        // into the history of HEAD, just like local tags, which requires their
+        // commit to be known locally.
+        let history = if self.repository.global {
+            None
+        } else {
+            Some(reachable_from_head(&repository)?)
+        };
+
+        if let Some((name, version, commit)) = remote_tags
+            .iter()
+            .filter_map(|(name, oid)| {
+                let commit = repository.find_commit(*oid).ok().map(|commit| commit.id());
+                match (&history, commit) {
+                    (None, _) => Some((name, format.parse(name)?, commit)),
+                    (Some(history), Some(commit)) if history.contains(&commit) => {
+                        Some((name, format.parse(name)?, Some(commit)))
                     }
+                    _ => None,
                 }
-                new_version
+            })
+            .max_by(|a, b| a.1.cmp(&b.1))
+        {
+            if latest
+                .as_ref()
+                .is_none_or(|latest| version > latest.version)
+            {
+                // The commit of the remote tag might not be known locally, in
+                // which case changes are still counted from the local version.
+                let Some(commit) = commit.or(latest.as_ref().map(|latest| latest.commit)) else {
+                    bail!(
+                        "the latest version {name} on {} is not known locally, fetch its \
+                        tags and try again",
+                        self.remote
+                    );
+                };
+
+                latest = Some(VersionTag {
+                    name: name.clone(),
+                    version,
+                    commit,
+                });
+            }
+        }
+
+        // HEAD only counts as already released if it is tagged with the requested
+        // version, or with any version if none was requested.
+        let tagged = head_versions(&repository, &format)?;
+        let existing = match requested {
+            Some(requested) => tagged.iter().find(|tag| &tag.version == requested),
+            None => tagged.last(),
+        };
+
+        if let Some(existing) = existing {
+            if !self.idempotent {
+                bail!(
+                    "HEAD is already tagged as {}, use --idempotent to print it instead",
+                    existing.name
+                );
+            }
+
+            let release = Release {
+                previous_version: Some(existing.version.clone()),
+                version: existing.version.clone(),
+                component: None,
+                commit: Some(existing.commit.to_string()),
+                tag: existing.name.clone(),
+                pushed: false,
             };
 
-            if !bump.dry_run {
-                let signature = repository.signature()?;
+            return self.report(&release, quiet);
+        }
+
+        if let (Some(requested), Some(other)) = (requested, tagged.last()) {
+            if !force {
+                bail!(
+                    "HEAD is already tagged as {}, use --force to tag it as {} as well",
+                    other.name,
+                    format.format(requested)
+                );
+            }
+        }
+
+        let (component, new_version) = next_version(&repository, latest.as_ref())?;
+        let tag_name = format.format(&new_version);
+
+        if remote_tags.contains_key(&tag_name) {
+            bail!(
+                "tag {tag_name} already exists on {}, fetch its tags and try again",
+                self.remote
+            );
+        }
+
+        if !self.allow_dirty {
+            let dirty = uncommitted_changes(&repository)?;
+            if !dirty.is_empty() {
+                let files = dirty.join("\n    ");
+
+                if !self.dry_run {
+                    bail!(
+                        "uncommitted changes found, commit them or use --allow-dirty:\n    {files}"
+                    );
+                }
+
+                eprintln!("warning: uncommitted changes found:\n    {files}");
+            }
+        }
+
+        let changes = self.file_changes(&repository, latest.as_ref(), &new_version)?;
+        let mut commit = None;
+        let mut pushed = false;
+
+        if self.dry_run {
+            let paths = changes.as_ref().map(Changes::paths).unwrap_or_default();
+            if !self.commits_files() || paths.is_empty() {
+                commit = Some(repository.head()?.peel_to_commit()?.id());
+            }
+
+            if let Some(changes) = &changes {
+                eprint!("{}", changes.diff());
+            }
+        } else {
+            // The annotation is built before any release commit is made, so it
+            // isn't listed among the changes since the latest version.
+            let message = if self.lightweight {
+                String::new()
+            } else {
+                self.tag_message(&repository, latest.as_ref())?
+            };
 
-                let tag = repository.find_tag(repository.tag(
-                    &format!("{new_version}"),
-                    &repository.head()?.peel(ObjectType::Commit)?,
-                    &signature,
-                    "",
-                    false,
-                )?)?;
+            let original = repository.head()?.peel_to_commit()?.id();
+            let mut committed = Vec::new();
+            if let Some(changes) = &changes {
+                changes.write()?;
 
-                if bump.push {
-                    let mut remote = repository.find_remote(&bump.remote)?;
+                let paths = changes.paths();
+                if self.commits_files() && !paths.is_empty() {
+                    let message = self
+                        .commit_message
+                        .replace("{version}", &new_version.to_string())
+                        .replace("{tag}", &tag_name);
+
+                    commit_paths(&repository, &paths, &message)?;
+                    committed = paths.into_iter().cloned().collect();
+                }
+            }
 
-                    let mut callbacks = RemoteCallbacks::new();
-                    callbacks.credentials(|_, username, credential_type| {
-                        let username = username.unwrap_or("git");
+            let target = repository.head()?.peel(ObjectType::Commit)?;
+            commit = Some(target.id());
 
-                        if credential_type.contains(CredentialType::USERNAME) {
-                            return Cred::username(username);
+            if self.lightweight {
+                repository.tag_lightweight(&tag_name, &target, false)?;
+            } else {
+                let signature = repository.signature()?;
+                repository.tag(&tag_name, &target, &signature, &message, false)?;
+            }
+
+            if self.push {
+                if let Err(error) = remote::push(
+                    &repository,
+                    &self.remote,
+                    &[format!("refs/tags/{tag_name}")],
+                    self.ssh_key.as_deref(),
+                ) {
+                    if self.keep_on_failure {
+                        return Err(error.context(format!(
+                            "the tag {tag_name} was created, but could not be pushed"
+                        )));
+                    }
+
+                    repository
+                        .tag_delete(&tag_name)
+                        .with_context(|| format!("failed to delete the tag {tag_name}"))?;
+
+                    if committed.is_empty() {
+                        // Files such as the changelog might have been written
+                        // without being committed, which would otherwise make
+                        // the next attempt fail due to uncommitted changes.
+                        if let Some(changes) = &changes {
+                            changes.restore()?;
                         }
 
-                        Cred::ssh_key_from_agent(username)
-                    });
+                        return Err(error.context(format!(
+                            "the tag {tag_name} could not be pushed, so it was deleted again"
+                        )));
+                    }
+
+                    undo_commit(&repository, original, &committed)
+                        .with_context(|| "failed to undo the release commit")?;
+
+                    return Err(error.context(format!(
+                        "the tag {tag_name} could not be pushed, so it and the release commit \
+                        were deleted again"
+                    )));
+                }
+
+                pushed = true;
+
+                // The release commit is only reachable from the tag on the remote,
+                // unless the branch it was committed to is pushed as well. This is
+                // done after pushing the tag, so the commit never ends up on the
+                // remote branch if the tag is rejected and the commit is undone.
+                let head = repository.head()?;
+                if let Some(branch) = head
+                    .name()
+                    .filter(|_| !committed.is_empty() && head.is_branch())
+                {
+                    remote::push(
+                        &repository,
+                        &self.remote,
+                        &[branch.to_string()],
+                        self.ssh_key.as_deref(),
+                    )
+                    .with_context(|| {
+                        format!(
+                            "the tag {tag_name} was pushed, but the branch with the release \
+                            commit could not be"
+                        )
+                    })?;
+                }
+            }
+        }
+
+        let release = Release {
+            previous_version: latest.map(|latest| latest.version),
+            version: new_version,
+            component,
+            commit: commit.map(|commit| commit.to_string()),
+            tag: tag_name,
+            pushed,
+        };
 
-                    let mut push_options = PushOptions::new();
-                    push_options.remote_callbacks(callbacks);
+        self.report(&release, quiet)
+    }
+}
 
-                    remote.push(
-                        &[&format!(
-                            "refs/tags/{}",
-                            tag.name()
-                                .with_context(|| "tag was just created, but has no name?")?
-                        )],
-                        Some(&mut push_options),
-                    )?;
+impl BumpCommand {
+    fn run(&self, quiet: bool) -> Result<(), anyhow::Error> {
+        self.release.run(quiet, None, false, |repository, latest| {
+            let latest = latest.with_context(|| "No semantic versioning tags found")?;
+            let (component, new_version) = self.version.next_version(repository, latest)?;
+            Ok((Some(component), new_version))
+        })
+    }
+}
 
-                    remote.disconnect()?;
+impl SetCommand {
+    fn run(&self, quiet: bool) -> Result<(), anyhow::Error> {
+        self.release.run(quiet, Some(&self.version), self.force, |_, latest| {
+            if let Some(latest) = latest.filter(|latest| self.version <= latest.version) {
+                if !self.force {
+                    bail!(
+                        "{} is not higher than the latest version {}, use --force to create it anyway",
+                        self.version,
+                        latest.name
+                    );
                 }
             }
 
-            if !opts.quiet {
-                println!("{new_version}");
+            Ok((None, self.version.clone()))
+        })
+    }
+}
+
+impl ChangelogCommand {
+    fn run(&self) -> Result<(), anyhow::Error> {
+        let (repository, format) = self.repository.open()?;
+        let latest = self.repository.latest(&repository, &format)?;
+        let (_, new_version) = self.version.next_version(&repository, &latest)?;
+
+        let section = changelog::section(
+            &repository,
+            &new_version,
+            &commits_since(&repository, Some(latest.commit))?,
+        )?;
+
+        if self.dry_run {
+            print!("{section}");
+        } else {
+            let workdir = repository
+                .workdir()
+                .with_context(|| "repository has no working directory")?;
+            let mut changes = Changes::new(workdir);
+
+            let content = changes.content(&self.file)?;
+            *content = changelog::prepend(content, &section);
+            changes.write()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CurrentCommand {
+    fn run(&self) -> Result<(), anyhow::Error> {
+        let (repository, format) = self.repository.open()?;
+        let latest = self.repository.latest(&repository, &format)?;
+
+        println!("{} {}", latest.name, latest.commit);
+
+        Ok(())
+    }
+}
+
+impl ListCommand {
+    fn run(&self) -> Result<(), anyhow::Error> {
+        let (repository, format) = self.repository.open()?;
+        let mut tags = version_tags(&repository, &format)?;
+
+        if self.reachable {
+            let history = reachable_from_head(&repository)?;
+            tags.retain(|tag| history.contains(&tag.commit));
+        }
+
+        tags.retain(|tag| {
+            (!self.exclude_prereleases || tag.version.pre.is_empty())
+                && self.major.is_none_or(|major| tag.version.major == major)
+        });
+        tags.sort_by(|a, b| a.version.cmp(&b.version));
+
+        let mut rows = Vec::new();
+        for tag in tags {
+            let commit = repository.find_commit(tag.commit)?;
+            let mut row = vec![tag.name.clone()];
+
+            for column in &self.columns {
+                row.push(match column {
+                    Column::Date => changelog::format_date(commit.time()),
+                    Column::Tagger => repository
+                        .find_reference(&format!("refs/tags/{}", tag.name))?
+                        .peel_to_tag()
+                        .ok()
+                        .and_then(|annotated| annotated.tagger()?.name().map(str::to_string))
+                        .unwrap_or_else(|| String::from("-")),
+                    Column::Sha => commit
+                        .as_object()
+                        .short_id()?
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string(),
+                });
+            }
+
+            rows.push(row);
+        }
+
+        // Every column is padded to its widest value, so they line up.
+        let mut widths = vec![0; self.columns.len() + 1];
+        for row in &rows {
+            for (width, value) in widths.iter_mut().zip(row) {
+                *width = (*width).max(value.chars().count());
             }
         }
+
+        for row in &rows {
+            let line: Vec<_> = row
+                .iter()
+                .zip(&widths)
+                .map(|(value, width)| format!("{value:width$}"))
+                .collect();
+
+            println!("{}", line.join("  ").trim_end());
+        }
+
+        Ok(())
+    }
+}
+
+impl DescribeCommand {
+    fn run(&self) -> Result<(), anyhow::Error> {
+        let (repository, format) = self.repository.open()?;
+        let latest = self.repository.latest(&repository, &format)?;
+
+        let distance = commits_since(&repository, Some(latest.commit))?.len();
+        let dirty = !uncommitted_changes(&repository)?.is_empty();
+
+        if distance == 0 && !dirty {
+            println!("{}", latest.version);
+            return Ok(());
+        }
+
+        let next = if latest.version.pre.is_empty() {
+            increment(&latest.version, &Component::Patch, None)?
+        } else {
+            increment(&latest.version, &Component::Release, None)?
+        };
+
+        let pre = if latest.version.pre.is_empty() {
+            String::from("-")
+        } else {
+            format!("-{}.", latest.version.pre)
+        };
+
+        let head = repository.head()?.peel_to_commit()?;
+        let sha = head.as_object().short_id()?;
+
+        println!(
+            "{}",
+            self.format
+                .replace("{version}", &latest.version.to_string())
+                .replace("{next}", &next.to_string())
+                .replace("{pre}", &pre)
+                .replace("{distance}", &distance.to_string())
+                .replace("{sha}", sha.as_str().unwrap_or_default())
+                .replace("{dirty}", if dirty { ".dirty" } else { "" })
+        );
+
+        Ok(())
     }
+}
+
+impl Commands {
+    fn repository(&self) -> &RepositoryArgs {
+        match self {
+            Commands::Bump(bump) => &bump.release.repository,
+            Commands::Changelog(changelog) => &changelog.repository,
+            Commands::Current(current) => &current.repository,
+            Commands::List(list) => &list.repository,
+            Commands::Describe(describe) => &describe.repository,
+            Commands::Set(set) => &set.release.repository,
+        }
+    }
+}
+
+/// Parses the command-line arguments, using the options configured for the
+/// repository as defaults.
+fn parse() -> Result<Opts, anyhow::Error> {
+    let args: Vec<_> = std::env::args_os().collect();
 
-    Ok(())
+    let command = config::negatable(Opts::command());
+
+    let matches = command.clone().get_matches_from(&args);
+    let opts = Opts::from_arg_matches(&matches)?;
+    if matches.subcommand_name().is_none() {
+        return Ok(opts);
+    }
+
+    let config = Config::load(&opts.subcommand.repository().repository()?)?;
+    let args = config.apply(&command, &matches, &args)?;
+
+    let matches = command.get_matches_from(args);
+    Ok(Opts::from_arg_matches(&matches)?)
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    let opts = parse()?;
+
+    match &opts.subcommand {
+        Commands::Bump(bump) => bump.run(opts.quiet),
+        Commands::Changelog(changelog) => changelog.run(),
+        Commands::Current(current) => current.run(),
+        Commands::List(list) => list.run(),
+        Commands::Describe(describe) => describe.run(),
+        Commands::Set(set) => set.run(opts.quiet),
+    }
 }
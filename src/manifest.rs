@@ -0,0 +1,639 @@
+use std::{
+    collections::HashMap,
+    fs,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use git2::Repository;
+use semver::Version;
+
+use crate::files::Changes;
+
+/// Kinds of manifest files whose version field can be updated.
+#[derive(Clone, Copy)]
+enum Kind {
+    Cargo,
+    Npm,
+    Python,
+    Helm,
+}
+
+impl Kind {
+    const ALL: [(&'static str, Kind); 4] = [
+        ("Cargo.toml", Kind::Cargo),
+        ("package.json", Kind::Npm),
+        ("pyproject.toml", Kind::Python),
+        ("Chart.yaml", Kind::Helm),
+    ];
+
+    fn of(path: &Path) -> Option<Kind> {
+        let name = path.file_name()?.to_str()?;
+        Kind::ALL
+            .iter()
+            .find(|(file_name, _)| *file_name == name)
+            .map(|(_, kind)| *kind)
+    }
+
+    /// Locates the version value within the contents of the manifest.
+    fn version(&self, content: &str) -> Option<Range<usize>> {
+        match self {
+            Kind::Cargo => toml_string(content, &["package", "workspace.package"], "version"),
+            Kind::Python => toml_string(content, &["project", "tool.poetry"], "version"),
+            Kind::Npm => json_version(content),
+            Kind::Helm => yaml_version(content),
+        }
+    }
+}
+
+/// Finds the manifests at the root of the working directory, along with the
+/// members of the Cargo workspace if there is one.
+fn discover(workdir: &Path) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let mut manifests: Vec<_> = Kind::ALL
+        .iter()
+        .map(|(name, _)| PathBuf::from(name))
+        .filter(|path| workdir.join(path).is_file())
+        .collect();
+
+    let cargo = workdir.join("Cargo.toml");
+    if cargo.is_file() {
+        let content = fs::read_to_string(&cargo)
+            .with_context(|| format!("failed to read {}", cargo.display()))?;
+
+        for member in workspace_members(&content) {
+            for directory in expand(workdir, &member) {
+                let manifest = directory.join("Cargo.toml");
+                if workdir.join(&manifest).is_file() && !manifests.contains(&manifest) {
+                    manifests.push(manifest);
+                }
+            }
+        }
+    }
+
+    Ok(manifests)
+}
+
+/// Sets the version field of every manifest found in the working directory,
+/// returning the paths of the manifests which were updated, relative to it.
+///
+/// Manifests without a version field, such as members inheriting their
+/// version from the Cargo workspace, are left untouched. If Cargo packages
+/// were updated, including those inheriting the version of an updated
+/// workspace, their entries in Cargo.lock are too, if it is tracked by git.
+pub fn update(
+    changes: &mut Changes,
+    repository: &Repository,
+    version: &Version,
+) -> Result<Vec<PathBuf>, anyhow::Error> {
+    let workdir = repository
+        .workdir()
+        .with_context(|| "repository has no working directory")?;
+    let new_version = version.to_string();
+    let mut updated = Vec::new();
+    let mut crates = HashMap::new();
+    let mut workspace_version = None;
+
+    for manifest in discover(workdir)? {
+        let Some(kind) = Kind::of(&manifest) else {
+            continue;
+        };
+
+        let content = changes.content(&manifest)?;
+
+        if let Kind::Cargo = kind {
+            if update_cargo(content, &new_version, &mut workspace_version, &mut crates) {
+                updated.push(manifest);
+            }
+            continue;
+        }
+
+        let Some(range) = kind.version(content) else {
+            continue;
+        };
+
+        content.replace_range(range, &new_version);
+        updated.push(manifest);
+    }
+
+    // Cargo.lock is often ignored, particularly by libraries, in which case it
+    // is left alone rather than committed along with the manifests.
+    let lockfile = Path::new("Cargo.lock");
+    if !crates.is_empty() && repository.index()?.get_path(lockfile, 0).is_some() {
+        let content = changes.content(lockfile)?;
+        *content = update_lockfile(content, &crates, &new_version);
+    }
+
+    Ok(updated)
+}
+
+/// Sets the version within both the `[package]` and `[workspace.package]`
+/// tables of a Cargo.toml, returning whether either of them was updated.
+///
+/// The name and previous version of the package are recorded in crates, so
+/// its entry in Cargo.lock can be updated. Packages inheriting their version
+/// from the workspace are recorded with the previous workspace version, which
+/// is expected to have been recorded from the root manifest beforehand.
+fn update_cargo(
+    content: &mut String,
+    version: &str,
+    workspace_version: &mut Option<String>,
+    crates: &mut HashMap<String, String>,
+) -> bool {
+    let package = toml_string(content, &["package"], "version");
+    let workspace = toml_string(content, &["workspace.package"], "version");
+
+    if let Some(range) = &workspace {
+        *workspace_version = Some(content[range.clone()].to_string());
+    }
+
+    let previous = match &package {
+        Some(range) => Some(content[range.clone()].to_string()),
+        None if inherits_workspace_version(content) => workspace_version.clone(),
+        None => None,
+    };
+
+    if let (Some(name), Some(previous)) = (toml_string(content, &["package"], "name"), previous) {
+        crates.insert(content[name].to_string(), previous);
+    }
+
+    // The later of the two ranges is replaced first, so the offsets of the
+    // other one remain valid.
+    let mut ranges: Vec<_> = package.into_iter().chain(workspace).collect();
+    ranges.sort_by_key(|range| std::cmp::Reverse(range.start));
+
+    for range in &ranges {
+        content.replace_range(range.clone(), version);
+    }
+
+    !ranges.is_empty()
+}
+
+/// Whether the `[package]` table of a Cargo.toml inherits its version from
+/// the workspace, using either `version.workspace = true` or
+/// `version = { workspace = true }`.
+fn inherits_workspace_version(content: &str) -> bool {
+    let mut table = String::new();
+
+    content.lines().any(|line| {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            table = trimmed.to_string();
+            return false;
+        }
+
+        let compact: String = trimmed.chars().filter(|c| !c.is_whitespace()).collect();
+        table == "[package]"
+            && (compact.starts_with("version.workspace=true")
+                || compact.starts_with("version={workspace=true"))
+    })
+}
+
+/// Locates the string value of the key within one of the given tables of a
+/// TOML document, excluding the quotes.
+fn toml_string(content: &str, tables: &[&str], key: &str) -> Option<Range<usize>> {
+    let mut table = String::new();
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') {
+            table = trimmed
+                .trim_start_matches('[')
+                .split(']')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string();
+            continue;
+        }
+
+        if !tables.contains(&table.as_str()) {
+            continue;
+        }
+
+        let Some(value) = trimmed
+            .strip_prefix(key)
+            .and_then(|rest| rest.trim_start().strip_prefix('='))
+            .map(str::trim_start)
+        else {
+            continue;
+        };
+
+        let Some(quote) = value.chars().next().filter(|c| *c == '"' || *c == '\'') else {
+            continue;
+        };
+
+        let open = start + line.find(quote)? + 1;
+        let close = open + content[open..].find(quote)?;
+        return Some(open..close);
+    }
+
+    None
+}
+
+/// Extracts the members of the workspace from the contents of a Cargo.toml.
+fn workspace_members(content: &str) -> Vec<String> {
+    let mut members = Vec::new();
+    let mut table = "";
+    let mut in_members = false;
+
+    for line in content.lines() {
+        let trimmed = line.trim();
+
+        if in_members {
+            members.extend(quoted_strings(trimmed));
+            in_members = !trimmed.contains(']');
+            continue;
+        }
+
+        if trimmed.starts_with('[') {
+            table = trimmed;
+            continue;
+        }
+
+        if table != "[workspace]" {
+            continue;
+        }
+
+        let Some(rest) = trimmed
+            .strip_prefix("members")
+            .and_then(|rest| rest.trim_start().strip_prefix('='))
+        else {
+            continue;
+        };
+
+        members.extend(quoted_strings(rest));
+        in_members = !rest.contains(']');
+    }
+
+    members
+}
+
+/// Returns the contents of every double-quoted string on the line.
+fn quoted_strings(line: &str) -> impl Iterator<Item = String> + '_ {
+    line.split('"').skip(1).step_by(2).map(str::to_string)
+}
+
+/// Expands a workspace member path relative to the workdir, where each path
+/// component can contain '*' and '?' wildcards.
+fn expand(workdir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::new()];
+
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        if !component.contains(['*', '?']) {
+            paths = paths.into_iter().map(|path| path.join(component)).collect();
+            continue;
+        }
+
+        let mut expanded = Vec::new();
+        for path in paths {
+            let Ok(entries) = fs::read_dir(workdir.join(&path)) else {
+                continue;
+            };
+
+            for entry in entries.flatten() {
+                let name = entry.file_name();
+                let Some(name) = name.to_str() else {
+                    continue;
+                };
+
+                if entry.path().is_dir() && wildcard_match(component, name) {
+                    expanded.push(path.join(name));
+                }
+            }
+        }
+
+        expanded.sort();
+        paths = expanded;
+    }
+
+    paths
+}
+
+/// Matches the name against a pattern containing '*' and '?' wildcards.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => {
+            let rest = &pattern[1..];
+            name.char_indices()
+                .map(|(index, _)| index)
+                .chain([name.len()])
+                .any(|index| wildcard_match(rest, &name[index..]))
+        }
+        Some(expected) => {
+            let mut chars = name.chars();
+            match chars.next() {
+                Some(c) if expected == '?' || expected == c => {
+                    wildcard_match(&pattern[expected.len_utf8()..], chars.as_str())
+                }
+                _ => false,
+            }
+        }
+    }
+}
+
+/// Locates the string value of the top-level "version" key of a JSON document,
+/// excluding the quotes.
+fn json_version(content: &str) -> Option<Range<usize>> {
+    let bytes = content.as_bytes();
+
+    // Finds the closing quote of the string starting at the given index.
+    let string_end = |mut index: usize| -> Option<usize> {
+        while index < bytes.len() {
+            match bytes[index] {
+                b'\\' => index += 2,
+                b'"' => return Some(index),
+                _ => index += 1,
+            }
+        }
+        None
+    };
+
+    let mut depth = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            b'"' => {
+                let start = index + 1;
+                let end = string_end(start)?;
+
+                if depth == 1 && &content[start..end] == "version" {
+                    let value = content[end + 1..]
+                        .trim_start()
+                        .strip_prefix(':')
+                        .map(str::trim_start)
+                        .filter(|value| value.starts_with('"'));
+
+                    if let Some(value) = value {
+                        let open = content.len() - value.len() + 1;
+                        return Some(open..string_end(open)?);
+                    }
+                }
+
+                index = end;
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+
+    None
+}
+
+/// Locates the value of the top-level "version" key of a YAML document,
+/// excluding any quotes.
+fn yaml_version(content: &str) -> Option<Range<usize>> {
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+
+        let Some(value) = line.strip_prefix("version:") else {
+            continue;
+        };
+
+        let open = start + line.len() - value.trim_start().len();
+        let value = value.trim_start();
+
+        return match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let open = open + 1;
+                Some(open..open + content[open..].find(quote)?)
+            }
+            _ => Some(open..open + value.find(char::is_whitespace).unwrap_or(value.len())),
+        };
+    }
+
+    None
+}
+
+/// Updates the version of the given local packages within a Cargo.lock,
+/// where each package is listed along with the version it had previously.
+fn update_lockfile(content: &str, crates: &HashMap<String, String>, version: &str) -> String {
+    let mut updated = String::with_capacity(content.len());
+    let mut package = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim();
+
+        if trimmed == "[[package]]" {
+            package = None;
+        } else if let Some(name) = trimmed.strip_prefix("name = ") {
+            package = crates.get(name.trim_matches('"'));
+        } else if let Some(previous) = package {
+            if trimmed == format!("version = \"{previous}\"") {
+                updated.push_str(&line.replacen(previous.as_str(), version, 1));
+                continue;
+            }
+        }
+
+        updated.push_str(line);
+    }
+
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::*;
+    use crate::testing::TestRepository;
+
+    fn value(content: &str, range: Option<Range<usize>>) -> Option<&str> {
+        range.map(|range| &content[range])
+    }
+
+    #[test]
+    fn finds_toml_string() {
+        let content = indoc! {r#"
+            [dependencies]
+            version = "0.1"
+
+            [package]
+            name = 'vergit'
+            version   =   "1.2.3" # comment
+        "#};
+
+        assert_eq!(
+            value(content, toml_string(content, &["package"], "version")),
+            Some("1.2.3")
+        );
+        assert_eq!(
+            value(content, toml_string(content, &["package"], "name")),
+            Some("vergit")
+        );
+        assert_eq!(
+            toml_string(content, &["workspace.package"], "version"),
+            None
+        );
+    }
+
+    #[test]
+    fn finds_json_version() {
+        let content = indoc! {r#"
+            {
+              "name": "with \"quotes\"",
+              "dependencies": { "version": "9.9.9" },
+              "version": "1.2.3"
+            }
+        "#};
+
+        assert_eq!(value(content, json_version(content)), Some("1.2.3"));
+        assert_eq!(json_version(r#"{"nested": {"version": "1.0.0"}}"#), None);
+    }
+
+    #[test]
+    fn finds_yaml_version() {
+        let content = "apiVersion: v2\nappVersion: 9.9.9\nversion: 1.2.3\n";
+        assert_eq!(value(content, yaml_version(content)), Some("1.2.3"));
+
+        let content = "dependencies:\n  version: 9.9.9\nversion: \"1.2.3\"\n";
+        assert_eq!(value(content, yaml_version(content)), Some("1.2.3"));
+    }
+
+    #[test]
+    fn extracts_workspace_members() {
+        let content = indoc! {r#"
+            [workspace]
+            members = ["cli", "crates/*"]
+
+            [workspace.metadata]
+            members = ["ignored"]
+        "#};
+        assert_eq!(workspace_members(content), ["cli", "crates/*"]);
+
+        let content = indoc! {r#"
+            [workspace]
+            members = [
+                "a",
+                "b",
+            ]
+        "#};
+        assert_eq!(workspace_members(content), ["a", "b"]);
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard_match("*", "anything"));
+        assert!(wildcard_match("crate-*", "crate-a"));
+        assert!(wildcard_match("crate-?", "crate-b"));
+        assert!(wildcard_match("*-cli", "vergit-cli"));
+        assert!(!wildcard_match("crate-?", "crate-ab"));
+        assert!(!wildcard_match("crate-*", "other"));
+    }
+
+    #[test]
+    fn updates_lockfile_entries() {
+        let content = indoc! {r#"
+            [[package]]
+            name = "a"
+            version = "1.0.0"
+
+            [[package]]
+            name = "serde"
+            version = "1.0.0"
+        "#};
+
+        let crates = HashMap::from([(String::from("a"), String::from("1.0.0"))]);
+        let updated = update_lockfile(content, &crates, "1.0.1");
+
+        assert_eq!(updated, content.replacen("1.0.0", "1.0.1", 1));
+    }
+
+    #[test]
+    fn updates_virtual_workspace_and_inheriting_members() {
+        let mut root = String::from(indoc! {r#"
+            [workspace]
+            members = ["a"]
+
+            [workspace.package]
+            version = "1.0.0"
+        "#});
+        let mut member = String::from(indoc! {r#"
+            [package]
+            name = "a"
+            version.workspace = true
+        "#});
+
+        let mut workspace_version = None;
+        let mut crates = HashMap::new();
+
+        assert!(update_cargo(
+            &mut root,
+            "1.0.1",
+            &mut workspace_version,
+            &mut crates
+        ));
+        assert!(root.contains(r#"version = "1.0.1""#));
+        assert!(crates.is_empty());
+
+        let original = member.clone();
+        assert!(!update_cargo(
+            &mut member,
+            "1.0.1",
+            &mut workspace_version,
+            &mut crates
+        ));
+        assert_eq!(member, original);
+        assert_eq!(crates.get("a").map(String::as_str), Some("1.0.0"));
+    }
+
+    #[test]
+    fn updates_package_and_workspace_versions() {
+        let mut root = String::from(indoc! {r#"
+            [package]
+            name = "root"
+            version = "1.0.0"
+
+            [workspace.package]
+            version = "1.0.0"
+        "#});
+
+        let mut crates = HashMap::new();
+        assert!(update_cargo(&mut root, "2.0.0", &mut None, &mut crates));
+        assert!(!root.contains("1.0.0"));
+        assert_eq!(crates.get("root").map(String::as_str), Some("1.0.0"));
+    }
+
+    #[test]
+    fn detects_inherited_version() {
+        assert!(inherits_workspace_version(
+            "[package]\nversion = { workspace = true }\n"
+        ));
+        assert!(!inherits_workspace_version(
+            "[package]\nversion = \"1.0.0\"\n"
+        ));
+        assert!(!inherits_workspace_version(
+            "[package]\n[dependencies]\nversion.workspace = true\n"
+        ));
+    }
+
+    #[test]
+    fn updates_lockfile_only_if_tracked() {
+        let test = TestRepository::new();
+        let manifest = "[package]\nname = \"a\"\nversion = \"1.0.0\"\n";
+        let lockfile = "[[package]]\nname = \"a\"\nversion = \"1.0.0\"\n";
+        test.write("Cargo.toml", manifest);
+        fs::write(test.path().join("Cargo.lock"), lockfile).unwrap();
+
+        let version = Version::parse("1.0.1").unwrap();
+        let mut changes = Changes::new(test.path());
+        let updated = update(&mut changes, &test.repository, &version).unwrap();
+        assert_eq!(updated, [PathBuf::from("Cargo.toml")]);
+        assert_eq!(changes.paths(), [&PathBuf::from("Cargo.toml")]);
+
+        test.write("Cargo.lock", lockfile);
+        let mut changes = Changes::new(test.path());
+        update(&mut changes, &test.repository, &version).unwrap();
+        assert_eq!(changes.paths().len(), 2);
+    }
+}
@@ -0,0 +1,77 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use semver::Version;
+use serde::Serialize;
+
+use crate::bump::Component;
+
+/// Formats in which the result of a command can be printed.
+#[derive(Clone, Copy, ValueEnum, Default)]
+pub enum Output {
+    /// Only the new version
+    #[default]
+    Text,
+    /// A JSON object describing the release
+    Json,
+}
+
+/// Outcome of creating a version, as printed to stdout.
+#[derive(Serialize)]
+pub struct Release {
+    /// The latest version before this one, if there was any.
+    pub previous_version: Option<Version>,
+    pub version: Version,
+    /// The component which was incremented, if the version was bumped rather
+    /// than set explicitly or already tagged.
+    pub component: Option<Component>,
+    /// Id of the tagged commit. In dry-run mode, this is unknown if a release
+    /// commit would have been created.
+    pub commit: Option<String>,
+    pub tag: String,
+    pub pushed: bool,
+}
+
+impl Release {
+    pub fn print(&self, output: Output) -> Result<(), anyhow::Error> {
+        match output {
+            Output::Text => println!("{}", self.version),
+            Output::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+
+        Ok(())
+    }
+
+    /// Appends the release to a file as environment variables in dotenv
+    /// format, as read by `$GITHUB_OUTPUT` and GitLab's dotenv artifacts.
+    pub fn write_env(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let variables = [
+            (
+                "VERGIT_PREVIOUS",
+                self.previous_version
+                    .as_ref()
+                    .map(Version::to_string)
+                    .unwrap_or_default(),
+            ),
+            ("VERGIT_VERSION", self.version.to_string()),
+            ("VERGIT_MAJOR", self.version.major.to_string()),
+            ("VERGIT_MINOR", self.version.minor.to_string()),
+            ("VERGIT_PATCH", self.version.patch.to_string()),
+            ("VERGIT_PRERELEASE", self.version.pre.to_string()),
+            ("VERGIT_TAG", self.tag.clone()),
+        ];
+
+        let content: String = variables
+            .iter()
+            .map(|(name, value)| format!("{name}={value}\n"))
+            .collect();
+
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(content.as_bytes()))
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use anyhow::{bail, Context};
+use git2::{
+    Config, Cred, CredentialType, Direction, Oid, PushOptions, RemoteCallbacks, Repository,
+};
+
+/// Sources of credentials, in the order they are tried. Each is only tried
+/// once per connection, so rejected credentials don't cause an endless loop.
+#[derive(Clone, PartialEq)]
+enum Source {
+    Username,
+    Token,
+    AskPass,
+    CredentialHelper,
+    SshKey(PathBuf),
+    SshAgent,
+}
+
+impl Source {
+    /// Lists the sources to try, where the given ssh key takes precedence
+    /// over the ssh-agent, and the default keys of the user are tried last.
+    fn all(ssh_key: Option<&Path>) -> Vec<Source> {
+        let mut sources = vec![
+            Source::Username,
+            Source::Token,
+            Source::AskPass,
+            Source::CredentialHelper,
+        ];
+
+        sources.extend(ssh_key.map(|key| Source::SshKey(key.to_path_buf())));
+        sources.push(Source::SshAgent);
+
+        if let Some(home) = env::var_os("HOME").or_else(|| env::var_os("USERPROFILE")) {
+            let directory = Path::new(&home).join(".ssh");
+            sources.extend(
+                ["id_ed25519", "id_rsa"]
+                    .iter()
+                    .map(|name| directory.join(name))
+                    .filter(|key| key.is_file() && Some(key.as_path()) != ssh_key)
+                    .map(Source::SshKey),
+            );
+        }
+
+        sources
+    }
+
+    fn allowed(&self, allowed: CredentialType) -> bool {
+        match self {
+            Source::Username => allowed.contains(CredentialType::USERNAME),
+            Source::Token | Source::AskPass | Source::CredentialHelper => {
+                allowed.contains(CredentialType::USER_PASS_PLAINTEXT)
+            }
+            Source::SshKey(_) | Source::SshAgent => allowed.contains(CredentialType::SSH_KEY),
+        }
+    }
+
+    /// Attempts to produce credentials from this source, returning None if
+    /// the source is not available.
+    fn credentials(&self, config: &Config, url: &str, username: Option<&str>) -> Option<Cred> {
+        match self {
+            Source::Username => Cred::username(username.unwrap_or("git")).ok(),
+            Source::Token => {
+                let token = env::var("VERGIT_TOKEN").ok()?;
+                let username = env::var("VERGIT_USERNAME")
+                    .ok()
+                    .or_else(|| username.map(str::to_string))
+                    .unwrap_or_else(|| String::from("x-access-token"));
+
+                Cred::userpass_plaintext(&username, &token).ok()
+            }
+            Source::AskPass => {
+                let askpass = env::var("GIT_ASKPASS").ok()?;
+                let username = match username {
+                    Some(username) => username.to_string(),
+                    None => ask(&askpass, &format!("Username for '{url}': "))?,
+                };
+                let password = ask(&askpass, &format!("Password for '{url}': "))?;
+
+                Cred::userpass_plaintext(&username, &password).ok()
+            }
+            Source::CredentialHelper => Cred::credential_helper(config, url, username).ok(),
+            Source::SshKey(key) => {
+                let public_key = PathBuf::from(format!("{}.pub", key.display()));
+                let passphrase = env::var("VERGIT_SSH_PASSPHRASE").ok();
+
+                Cred::ssh_key(
+                    username.unwrap_or("git"),
+                    Some(public_key.as_path()).filter(|key| key.is_file()),
+                    key,
+                    passphrase.as_deref(),
+                )
+                .ok()
+            }
+            Source::SshAgent => Cred::ssh_key_from_agent(username.unwrap_or("git")).ok(),
+        }
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Username => write!(f, "username"),
+            Source::Token => write!(f, "token from $VERGIT_TOKEN"),
+            Source::AskPass => write!(f, "$GIT_ASKPASS"),
+            Source::CredentialHelper => write!(f, "git credential helpers"),
+            Source::SshKey(key) => write!(f, "ssh key {}", key.display()),
+            Source::SshAgent => write!(f, "ssh-agent"),
+        }
+    }
+}
+
+/// Runs an askpass program with the given prompt, returning its answer.
+fn ask(program: &str, prompt: &str) -> Option<String> {
+    let output = Command::new(program).arg(prompt).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    let answer = String::from_utf8(output.stdout).ok()?;
+    Some(answer.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Builds the callbacks used to authenticate against remotes.
+///
+/// For HTTPS remotes a token from the VERGIT_TOKEN environment variable is
+/// used first, with the username taken from VERGIT_USERNAME, the remote url,
+/// or defaulting to 'x-access-token'. After that the GIT_ASKPASS program is
+/// asked, and then git's configured credential helpers.
+///
+/// For SSH remotes the given key is used first, then the keys of the running
+/// ssh-agent, and then the default keys of the user: ~/.ssh/id_ed25519 and
+/// ~/.ssh/id_rsa. Keys are decrypted using VERGIT_SSH_PASSPHRASE, if set.
+fn callbacks(
+    repository: &Repository,
+    ssh_key: Option<&Path>,
+) -> Result<RemoteCallbacks<'static>, anyhow::Error> {
+    let config = repository.config()?.snapshot()?;
+    let sources = Source::all(ssh_key);
+    let mut tried = Vec::new();
+    let mut attempted = Vec::new();
+
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        for source in &sources {
+            if tried.contains(source) || !source.allowed(allowed) {
+                continue;
+            }
+
+            tried.push(source.clone());
+            if let Some(credentials) = source.credentials(&config, url, username) {
+                attempted.push(source.to_string());
+                return Ok(credentials);
+            }
+        }
+
+        Err(git2::Error::from_str(&if attempted.is_empty() {
+            format!("no credentials available for {url}")
+        } else {
+            format!(
+                "no accepted credentials for {url}, tried: {}",
+                attempted.join(", ")
+            )
+        }))
+    });
+
+    Ok(callbacks)
+}
+
+/// Pushes the references to the remote.
+pub fn push(
+    repository: &Repository,
+    remote: &str,
+    refspecs: &[String],
+    ssh_key: Option<&Path>,
+) -> Result<(), anyhow::Error> {
+    let mut remote = repository.find_remote(remote)?;
+
+    // References rejected by the remote don't fail the push by themselves,
+    // they are only reported through this callback.
+    let rejected = RefCell::new(Vec::new());
+    let mut callbacks = callbacks(repository, ssh_key)?;
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            rejected
+                .borrow_mut()
+                .push(format!("{reference} was rejected: {status}"));
+        }
+        Ok(())
+    });
+
+    let mut push_options = PushOptions::new();
+    push_options.remote_callbacks(callbacks);
+
+    let name = remote.name().unwrap_or("remote").to_string();
+    remote
+        .push(refspecs, Some(&mut push_options))
+        .with_context(|| format!("failed to push to {name}"))?;
+
+    drop(push_options);
+    let rejected = rejected.into_inner();
+    if !rejected.is_empty() {
+        bail!("failed to push to {name}: {}", rejected.join(", "));
+    }
+
+    remote.disconnect()?;
+    Ok(())
+}
+
+/// Lists the tags of the remote without fetching them, along with the id of
+/// the object each points to. Annotated tags are peeled to their target, as
+/// far as the remote advertises it.
+pub fn tags(
+    repository: &Repository,
+    remote: &str,
+    ssh_key: Option<&Path>,
+) -> Result<BTreeMap<String, Oid>, anyhow::Error> {
+    let mut remote = repository.find_remote(remote)?;
+    let name = remote.name().unwrap_or("remote").to_string();
+
+    let connection = remote
+        .connect_auth(
+            Direction::Fetch,
+            Some(callbacks(repository, ssh_key)?),
+            None,
+        )
+        .with_context(|| format!("failed to connect to {name}"))?;
+
+    let mut tags = BTreeMap::new();
+    for head in connection
+        .list()
+        .with_context(|| format!("failed to list the references of {name}"))?
+    {
+        let Some(tag) = head.name().strip_prefix("refs/tags/") else {
+            continue;
+        };
+
+        match tag.strip_suffix("^{}") {
+            Some(tag) => {
+                tags.insert(tag.to_string(), head.oid());
+            }
+            None => {
+                tags.entry(tag.to_string()).or_insert_with(|| head.oid());
+            }
+        }
+    }
+
+    Ok(tags)
+}
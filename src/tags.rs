@@ -0,0 +1,295 @@
+use std::{collections::HashSet, str::FromStr};
+
+use anyhow::Context;
+use git2::{Commit, Oid, Repository};
+use semver::Version;
+
+/// Describes how version tags are named within a repository, so versions can
+/// be extracted from existing tag names and new tag names can be produced.
+///
+/// Tags are named `<namespace>/<prefix><version>` when a namespace is used,
+/// and `<prefix><version>` otherwise.
+pub struct TagFormat {
+    pub namespace: Option<String>,
+    pub prefix: String,
+}
+
+impl TagFormat {
+    /// Builds the tag format for the repository, using the given prefix if one
+    /// is specified. Otherwise the prefix 'v' is used if every existing version
+    /// tag within the namespace consistently uses it, and no prefix at all if not.
+    pub fn detect(
+        repository: &Repository,
+        namespace: Option<&str>,
+        prefix: Option<&str>,
+    ) -> Result<Self, anyhow::Error> {
+        let namespace = namespace.map(|namespace| namespace.trim_end_matches('/').to_string());
+
+        if let Some(prefix) = prefix {
+            return Ok(TagFormat {
+                namespace,
+                prefix: prefix.to_string(),
+            });
+        }
+
+        let mut format = TagFormat {
+            namespace,
+            prefix: String::new(),
+        };
+
+        let (mut prefixed, mut bare) = (0, 0);
+        for name in repository.tag_names(None)?.iter().flatten() {
+            let Some(name) = format.strip_namespace(name) else {
+                continue;
+            };
+
+            if Version::from_str(name).is_ok() {
+                bare += 1;
+            } else if name
+                .strip_prefix('v')
+                .is_some_and(|name| Version::from_str(name).is_ok())
+            {
+                prefixed += 1;
+            }
+        }
+
+        if prefixed > 0 && bare == 0 {
+            format.prefix = "v".to_string();
+        }
+
+        Ok(format)
+    }
+
+    /// Removes the namespace from the tag name, returning None if the tag does
+    /// not belong to the namespace.
+    fn strip_namespace<'a>(&self, name: &'a str) -> Option<&'a str> {
+        match &self.namespace {
+            Some(namespace) => name
+                .strip_prefix(namespace.as_str())
+                .and_then(|name| name.strip_prefix('/')),
+            None => Some(name),
+        }
+    }
+
+    /// Extracts the version from a tag name, if it matches this format.
+    pub fn parse(&self, name: &str) -> Option<Version> {
+        self.strip_namespace(name)
+            .and_then(|name| name.strip_prefix(self.prefix.as_str()))
+            .and_then(|version| Version::from_str(version).ok())
+    }
+
+    /// Produces the tag name for the given version.
+    pub fn format(&self, version: &Version) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}/{}{version}", self.prefix),
+            None => format!("{}{version}", self.prefix),
+        }
+    }
+}
+
+/// A tag whose name matches the tag format.
+pub struct VersionTag {
+    pub name: String,
+    pub version: Version,
+    /// Id of the commit the tag ultimately points to.
+    pub commit: Oid,
+}
+
+/// Returns every tag in the repository which matches the tag format.
+pub fn version_tags(
+    repository: &Repository,
+    format: &TagFormat,
+) -> Result<Vec<VersionTag>, anyhow::Error> {
+    let mut versions = Vec::new();
+
+    for name in repository.tag_names(None)?.iter().flatten() {
+        let Some(version) = format.parse(name) else {
+            continue;
+        };
+
+        // Tags can technically point at trees and blobs too, those are never
+        // part of a branch history, so they're skipped entirely.
+        let Ok(commit) = repository
+            .revparse_single(&format!("refs/tags/{name}"))
+            .and_then(|object| object.peel_to_commit())
+        else {
+            continue;
+        };
+
+        versions.push(VersionTag {
+            name: name.to_string(),
+            version,
+            commit: commit.id(),
+        });
+    }
+
+    Ok(versions)
+}
+
+/// Finds the highest version tag in the entire repository if global is set,
+/// and otherwise the highest version tag reachable from HEAD.
+pub fn latest_version(
+    repository: &Repository,
+    format: &TagFormat,
+    global: bool,
+) -> Result<Option<VersionTag>, anyhow::Error> {
+    let mut all_versions = version_tags(repository, format)?;
+
+    if !global {
+        // Only consider tags pointing at commits reachable from HEAD. I previously
+        // used the git describe functionality for this, but if you had two tags
+        // pointing at the same commit for example, it would only return one of
+        // the tags, which meant if you had two tags like for instance:
+        //      0.0.10
+        //      0.0.9
+        //
+        // pointing to the same commit, Describe would not order them correctly
+        // according to the semver spec, instead using plain ASCIIbetical ordering,
+        // meaning 0.0.9 would incorrectly be considered the latest version.
+        let history = reachable_from_head(repository)?;
+        all_versions.retain(|tag| history.contains(&tag.commit));
+    }
+
+    Ok(all_versions
+        .into_iter()
+        .max_by(|a, b| a.version.cmp(&b.version)))
+}
+
+/// Returns the version tags pointing at HEAD, from lowest to highest.
+pub fn head_versions(
+    repository: &Repository,
+    format: &TagFormat,
+) -> Result<Vec<VersionTag>, anyhow::Error> {
+    let head = repository.head()?.peel_to_commit()?.id();
+
+    let mut tags = version_tags(repository, format)?;
+    tags.retain(|tag| tag.commit == head);
+    tags.sort_by(|a, b| a.version.cmp(&b.version));
+
+    Ok(tags)
+}
+
+/// Walks the history of the currently checked out commit, returning the ids
+/// of every commit reachable from HEAD, including HEAD itself.
+pub fn reachable_from_head(repository: &Repository) -> Result<HashSet<Oid>, anyhow::Error> {
+    let mut revwalk = repository.revwalk()?;
+    revwalk
+        .push_head()
+        .with_context(|| "failed to walk history from HEAD, is a branch checked out?")?;
+
+    Ok(revwalk.collect::<Result<_, _>>()?)
+}
+
+/// Returns the commits reachable from HEAD which are not reachable from the
+/// given commit, newest first. Without a commit, the entire history of HEAD
+/// is returned.
+pub fn commits_since(
+    repository: &Repository,
+    since: Option<Oid>,
+) -> Result<Vec<Commit<'_>>, anyhow::Error> {
+    let mut revwalk = repository.revwalk()?;
+    revwalk
+        .push_head()
+        .with_context(|| "failed to walk history from HEAD, is a branch checked out?")?;
+
+    if let Some(since) = since {
+        revwalk.hide(since)?;
+    }
+
+    revwalk.map(|id| Ok(repository.find_commit(id?)?)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestRepository;
+
+    fn format(namespace: Option<&str>, prefix: &str) -> TagFormat {
+        TagFormat {
+            namespace: namespace.map(str::to_string),
+            prefix: prefix.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_prefixed_tags() {
+        let prefixed = format(None, "v");
+        assert_eq!(prefixed.parse("v1.2.3"), Version::parse("1.2.3").ok());
+        assert_eq!(prefixed.parse("1.2.3"), None);
+        assert_eq!(prefixed.parse("v1.2"), None);
+
+        let bare = format(None, "");
+        assert_eq!(bare.parse("1.2.3-rc.1"), Version::parse("1.2.3-rc.1").ok());
+        assert_eq!(bare.parse("v1.2.3"), None);
+    }
+
+    #[test]
+    fn formats_prefixed_tags() {
+        let version = Version::parse("1.2.3").unwrap();
+        assert_eq!(format(None, "v").format(&version), "v1.2.3");
+        assert_eq!(format(None, "release-").format(&version), "release-1.2.3");
+    }
+
+    #[test]
+    fn parses_namespaced_tags() {
+        let networking = format(Some("networking"), "v");
+        assert_eq!(
+            networking.parse("networking/v1.4.0"),
+            Version::parse("1.4.0").ok()
+        );
+        assert_eq!(networking.parse("storage/v2.0.1"), None);
+        assert_eq!(networking.parse("networking-v1.4.0"), None);
+        assert_eq!(networking.parse("v1.4.0"), None);
+
+        let version = Version::parse("1.5.0").unwrap();
+        assert_eq!(networking.format(&version), "networking/v1.5.0");
+    }
+
+    #[test]
+    fn finds_latest_version_in_branch_history() {
+        let test = TestRepository::new();
+        test.commit("initial commit");
+        test.tag("1.4.0");
+        test.switch("maintenance");
+
+        test.switch("main");
+        test.commit("feat!: breaking change");
+        test.tag("2.0.0");
+
+        test.switch("maintenance");
+        test.commit("fix: backported fix");
+
+        let format = format(None, "");
+        let latest = |global| {
+            latest_version(&test.repository, &format, global)
+                .unwrap()
+                .map(|tag| tag.version.to_string())
+        };
+
+        assert_eq!(latest(false).as_deref(), Some("1.4.0"));
+        assert_eq!(latest(true).as_deref(), Some("2.0.0"));
+    }
+
+    #[test]
+    fn lists_versions_at_head() {
+        let test = TestRepository::new();
+        test.commit("initial commit");
+        test.tag("1.0.0");
+
+        test.commit("fix: first fix");
+        assert!(head_versions(&test.repository, &format(None, ""))
+            .unwrap()
+            .is_empty());
+
+        test.tag("1.0.10");
+        test.tag("1.0.9");
+        test.tag("not-a-version");
+
+        let versions: Vec<_> = head_versions(&test.repository, &format(None, ""))
+            .unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+        assert_eq!(versions, ["1.0.9", "1.0.10"]);
+    }
+}
@@ -0,0 +1,90 @@
+//! Helpers for tests operating on temporary git repositories.
+
+use std::{fs, path::Path};
+
+use git2::{Oid, Repository, Signature};
+use tempfile::TempDir;
+
+/// A git repository within a temporary directory, which is deleted on drop.
+pub struct TestRepository {
+    pub repository: Repository,
+    directory: TempDir,
+}
+
+impl TestRepository {
+    pub fn new() -> Self {
+        let directory = tempfile::tempdir().unwrap();
+        let repository = Repository::init(directory.path()).unwrap();
+
+        let mut config = repository.config().unwrap();
+        config.set_str("user.name", "vergit").unwrap();
+        config.set_str("user.email", "vergit@example.com").unwrap();
+
+        TestRepository {
+            repository,
+            directory,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        self.directory.path()
+    }
+
+    /// Writes the file to the working directory and stages it.
+    pub fn write(&self, path: &str, content: &str) {
+        fs::write(self.path().join(path), content).unwrap();
+
+        let mut index = self.repository.index().unwrap();
+        index.add_path(Path::new(path)).unwrap();
+        index.write().unwrap();
+    }
+
+    /// Commits the index on top of HEAD, returning the id of the commit.
+    pub fn commit(&self, message: &str) -> Oid {
+        let signature = Signature::now("vergit", "vergit@example.com").unwrap();
+        let tree_id = self.repository.index().unwrap().write_tree().unwrap();
+        let tree = self.repository.find_tree(tree_id).unwrap();
+
+        let parent = self
+            .repository
+            .head()
+            .ok()
+            .and_then(|head| head.peel_to_commit().ok());
+        let parents: Vec<_> = parent.iter().collect();
+
+        self.repository
+            .commit(
+                Some("HEAD"),
+                &signature,
+                &signature,
+                message,
+                &tree,
+                &parents,
+            )
+            .unwrap()
+    }
+
+    /// Creates a lightweight tag pointing at HEAD.
+    pub fn tag(&self, name: &str) {
+        let head = self.repository.head().unwrap().peel_to_commit().unwrap();
+        self.repository
+            .tag_lightweight(name, head.as_object(), false)
+            .unwrap();
+    }
+
+    /// Creates a branch at HEAD if it doesn't exist yet, and points HEAD at it.
+    pub fn switch(&self, branch: &str) {
+        if self
+            .repository
+            .find_branch(branch, git2::BranchType::Local)
+            .is_err()
+        {
+            let head = self.repository.head().unwrap().peel_to_commit().unwrap();
+            self.repository.branch(branch, &head, false).unwrap();
+        }
+
+        self.repository
+            .set_head(&format!("refs/heads/{branch}"))
+            .unwrap();
+    }
+}